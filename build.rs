@@ -0,0 +1,46 @@
+use std::process::Command;
+
+// Records what was built for the manifest of each result: the git commit,
+// the compiler, target and profile, and the version of ixa that Cargo.lock
+// resolved
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+
+    let commit = Command::new("git")
+        .args(["describe", "--always", "--dirty"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=SIR_BENCH_GIT_COMMIT={commit}");
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("-V")
+        .output()
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=SIR_BENCH_RUSTC_VERSION={rustc_version}");
+    for var in ["TARGET", "PROFILE", "OPT_LEVEL"] {
+        let value = std::env::var(var).unwrap_or_default();
+        println!("cargo:rustc-env=SIR_BENCH_{var}={value}");
+    }
+
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut lines = lock.lines();
+    let mut ixa_version = "unknown".to_string();
+    while let Some(line) = lines.next() {
+        if line == "name = \"ixa\""
+            && let Some(version) = lines.next().and_then(|l| l.strip_prefix("version = "))
+        {
+            ixa_version = version.trim_matches('"').to_string();
+            break;
+        }
+    }
+    println!("cargo:rustc-env=SIR_BENCH_IXA_VERSION={ixa_version}");
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+// Wraps the system allocator to count heap usage. It is installed as the
+// global allocator with the `count-allocations` feature, so that the default
+// build doesn't pay for the atomic updates.
+pub struct CountingAllocator;
+
+static CURRENT_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn record_alloc(size: usize) {
+    let current = CURRENT_BYTES.fetch_add(size, Relaxed) + size;
+    PEAK_BYTES.fetch_max(current, Relaxed);
+    TOTAL_BYTES.fetch_add(size, Relaxed);
+    ALLOCATIONS.fetch_add(1, Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT_BYTES.fetch_sub(layout.size(), Relaxed);
+    }
+
+    // Counted as a new allocation of the new size that frees the old one
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            CURRENT_BYTES.fetch_sub(layout.size(), Relaxed);
+            record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct AllocStats {
+    // Highest number of live heap bytes during the phase, including anything
+    // allocated before it started
+    pub peak_bytes: usize,
+    pub total_bytes: usize,
+    pub allocations: usize,
+}
+
+// Measures the allocations made between `start` and `finish`
+pub struct AllocPhase {
+    total_bytes: usize,
+    allocations: usize,
+}
+
+impl AllocPhase {
+    pub fn start() -> Self {
+        PEAK_BYTES.store(CURRENT_BYTES.load(Relaxed), Relaxed);
+        Self {
+            total_bytes: TOTAL_BYTES.load(Relaxed),
+            allocations: ALLOCATIONS.load(Relaxed),
+        }
+    }
+
+    // Returns None unless the counting allocator is installed
+    pub fn finish(self) -> Option<AllocStats> {
+        if !cfg!(feature = "count-allocations") {
+            return None;
+        }
+        Some(self.stats())
+    }
+
+    fn stats(&self) -> AllocStats {
+        AllocStats {
+            peak_bytes: PEAK_BYTES.load(Relaxed),
+            total_bytes: TOTAL_BYTES.load(Relaxed) - self.total_bytes,
+            allocations: ALLOCATIONS.load(Relaxed) - self.allocations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_allocations() {
+        let allocator = CountingAllocator;
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+
+        let phase = AllocPhase::start();
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            let ptr = allocator.realloc(ptr, layout, 8192);
+            allocator.dealloc(ptr, Layout::from_size_align(8192, 8).unwrap());
+        }
+        let stats = phase.stats();
+
+        // Other tests may allocate concurrently when the allocator is installed
+        assert!(stats.allocations >= 2);
+        assert!(stats.total_bytes >= 4096 + 8192);
+        assert!(stats.peak_bytes >= 8192);
+    }
+}
@@ -1,7 +1,18 @@
-use crate::stats::ModelStats;
+use crate::{
+    checkpoint::{CheckpointError, Checkpoints, ModelState},
+    invariants::InvariantViolation,
+    observer::SimulationObserver,
+    rng::RngKind,
+    sir::store::StoreKind,
+    stats::ModelStats,
+    stop::{StopConditions, StopReason},
+    trace::Trace,
+    transmission::TransmissionTree,
+};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Parameters {
     pub r0: f64,
     pub infectious_period: f64,
@@ -11,6 +22,42 @@ pub struct Parameters {
     pub max_time: f64,
     pub enable_stats: bool,
     pub disable_queries: bool,
+    #[serde(default)]
+    pub track_transmission: bool,
+    #[serde(default)]
+    pub profile_events: bool,
+    // Draw from `rng::SharedStream` so that every model gives the same
+    // events for the same seed
+    #[serde(default)]
+    pub common_random_numbers: bool,
+    #[serde(default)]
+    pub rng: RngKind,
+    // Register this many observers that do nothing, to measure the cost of
+    // notifying them
+    #[serde(default)]
+    pub noop_observers: usize,
+    #[serde(default)]
+    pub stop: StopConditions,
+    // Check the model's state after every event and stop at the first
+    // inconsistency, which the model keeps for the caller
+    #[serde(default)]
+    pub check_invariants: bool,
+    // Record a timeline of setup, batches of events, queries and reports
+    #[serde(default)]
+    pub trace: bool,
+    // Keep ixa's list of infectious people in an IndexSet, so that removing
+    // someone who recovers takes constant time instead of a scan
+    #[serde(default)]
+    pub infected_set: bool,
+    // How the baseline keeps its people and their statuses
+    #[serde(default)]
+    pub store: StoreKind,
+    // Draw infections at the rate contacts reach susceptible people and pick
+    // the contact among them, instead of drawing every contact and rejecting
+    // those who aren't susceptible. Gives the same epidemics in distribution
+    // with fewer events.
+    #[serde(default)]
+    pub direct_sampling: bool,
 }
 
 impl Default for Parameters {
@@ -24,14 +71,328 @@ impl Default for Parameters {
             max_time: 100.0,
             enable_stats: false,
             disable_queries: true,
+            track_transmission: false,
+            profile_events: false,
+            common_random_numbers: false,
+            rng: RngKind::Small,
+            noop_observers: 0,
+            stop: StopConditions::default(),
+            check_invariants: false,
+            trace: false,
+            infected_set: false,
+            store: StoreKind::IndexSet,
+            direct_sampling: false,
         }
     }
 }
 
+impl Parameters {
+    // Starts from the defaults
+    pub fn builder() -> ParametersBuilder {
+        ParametersBuilder {
+            params: Parameters::default(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ParameterError> {
+        if !(self.r0.is_finite() && self.r0 > 0.0) {
+            return Err(ParameterError::InvalidR0(self.r0));
+        }
+        if !(self.infectious_period.is_finite() && self.infectious_period > 0.0) {
+            return Err(ParameterError::InvalidInfectiousPeriod(
+                self.infectious_period,
+            ));
+        }
+        if self.initial_infections > self.population {
+            return Err(ParameterError::TooManyInitialInfections {
+                initial_infections: self.initial_infections,
+                population: self.population,
+            });
+        }
+        // ixa can't schedule the end of the simulation at an infinite time
+        if !(self.max_time.is_finite() && self.max_time >= 0.0) {
+            return Err(ParameterError::InvalidMaxTime(self.max_time));
+        }
+        self.stop.validate()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterError {
+    InvalidR0(f64),
+    InvalidInfectiousPeriod(f64),
+    TooManyInitialInfections {
+        initial_infections: usize,
+        population: usize,
+    },
+    InvalidMaxTime(f64),
+    InvalidWallClockBudget(f64),
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterError::InvalidR0(r0) => {
+                write!(f, "r0 must be a positive number, got {r0}")
+            }
+            ParameterError::InvalidInfectiousPeriod(period) => {
+                write!(
+                    f,
+                    "infectious_period must be a positive number, got {period}"
+                )
+            }
+            ParameterError::TooManyInitialInfections {
+                initial_infections,
+                population,
+            } => write!(
+                f,
+                "initial_infections ({initial_infections}) can't exceed the population ({population})"
+            ),
+            ParameterError::InvalidMaxTime(max_time) => {
+                write!(
+                    f,
+                    "max_time must be a non-negative finite number, got {max_time}"
+                )
+            }
+            ParameterError::InvalidWallClockBudget(seconds) => {
+                write!(
+                    f,
+                    "max_wall_seconds must be a non-negative finite number, got {seconds}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
+pub struct ParametersBuilder {
+    params: Parameters,
+}
+
+impl ParametersBuilder {
+    pub fn r0(mut self, r0: f64) -> Self {
+        self.params.r0 = r0;
+        self
+    }
+    pub fn infectious_period(mut self, infectious_period: f64) -> Self {
+        self.params.infectious_period = infectious_period;
+        self
+    }
+    pub fn population(mut self, population: usize) -> Self {
+        self.params.population = population;
+        self
+    }
+    pub fn initial_infections(mut self, initial_infections: usize) -> Self {
+        self.params.initial_infections = initial_infections;
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.params.seed = seed;
+        self
+    }
+    pub fn max_time(mut self, max_time: f64) -> Self {
+        self.params.max_time = max_time;
+        self
+    }
+    pub fn enable_stats(mut self, enable_stats: bool) -> Self {
+        self.params.enable_stats = enable_stats;
+        self
+    }
+    pub fn disable_queries(mut self, disable_queries: bool) -> Self {
+        self.params.disable_queries = disable_queries;
+        self
+    }
+    pub fn track_transmission(mut self, track_transmission: bool) -> Self {
+        self.params.track_transmission = track_transmission;
+        self
+    }
+    pub fn profile_events(mut self, profile_events: bool) -> Self {
+        self.params.profile_events = profile_events;
+        self
+    }
+    pub fn common_random_numbers(mut self, common_random_numbers: bool) -> Self {
+        self.params.common_random_numbers = common_random_numbers;
+        self
+    }
+    pub fn rng(mut self, rng: RngKind) -> Self {
+        self.params.rng = rng;
+        self
+    }
+    pub fn noop_observers(mut self, noop_observers: usize) -> Self {
+        self.params.noop_observers = noop_observers;
+        self
+    }
+    pub fn stop(mut self, stop: StopConditions) -> Self {
+        self.params.stop = stop;
+        self
+    }
+    pub fn check_invariants(mut self, check_invariants: bool) -> Self {
+        self.params.check_invariants = check_invariants;
+        self
+    }
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.params.trace = trace;
+        self
+    }
+    pub fn infected_set(mut self, infected_set: bool) -> Self {
+        self.params.infected_set = infected_set;
+        self
+    }
+    pub fn store(mut self, store: StoreKind) -> Self {
+        self.params.store = store;
+        self
+    }
+    pub fn direct_sampling(mut self, direct_sampling: bool) -> Self {
+        self.params.direct_sampling = direct_sampling;
+        self
+    }
+    pub fn build(self) -> Result<Parameters, ParameterError> {
+        self.params.validate()?;
+        Ok(self.params)
+    }
+}
+
 pub trait SIRModel {
     fn id(&self) -> &'static str;
     fn current_time(&self) -> f64;
-    fn run(&mut self);
+    // Validates the parameters, then builds the population and seeds the
+    // initial infections
+    fn setup(&mut self) -> Result<(), ParameterError>;
+    // Runs the epidemic loop on a model that has been set up
+    fn simulate(&mut self);
+    fn run(&mut self) -> Result<(), ParameterError> {
+        self.setup()?;
+        self.simulate();
+        Ok(())
+    }
+    // Returns to the state just after setup, with `seed` in place of the
+    // parameters' seed, so the model can be simulated again. Observers stay
+    // registered.
+    fn reset(&mut self, seed: u64) -> Result<(), ParameterError>;
     fn get_stats(&self) -> &ModelStats;
     fn get_params(&self) -> &Parameters;
+    fn get_transmission_tree(&self) -> Option<&TransmissionTree>;
+    // Only recorded with `trace`
+    fn get_trace(&self) -> Option<&Trace>;
+    // Why the last simulation ended, or None before simulating
+    fn stop_reason(&self) -> Option<StopReason>;
+    // What stopped the last simulation with `StopReason::Invariant`
+    fn invariant_violation(&self) -> Option<&InvariantViolation>;
+    // Notifies `observer` of every event from now on
+    fn add_observer(&mut self, observer: Box<dyn SimulationObserver>);
+    // Passes the model's state to the hook at the first event at or after
+    // each checkpoint time while simulating
+    fn set_checkpoints(&mut self, checkpoints: Checkpoints) -> Result<(), CheckpointError>;
+    // The current state, which `ModelKind::restore` continues from
+    fn checkpoint(&self) -> Result<ModelState, CheckpointError>;
+    // Starts the names of the report files the model writes itself, so that
+    // runs at the same time don't write to the same file. Set before setup.
+    fn set_report_prefix(&mut self, _prefix: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, observer::SimulationObserver};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Default)]
+    struct Finishes(usize);
+
+    impl SimulationObserver for Finishes {
+        fn on_finish(&mut self, _t: f64, _stats: &ModelStats) {
+            self.0 += 1;
+        }
+    }
+
+    fn outcome(model: &dyn SIRModel) -> (f64, usize, usize) {
+        (
+            model.current_time(),
+            model.get_stats().get_cum_incidence(),
+            model.get_transmission_tree().unwrap().len(),
+        )
+    }
+
+    #[test]
+    fn reset_matches_a_new_model() {
+        let params = Parameters {
+            population: 2000,
+            track_transmission: true,
+            trace: true,
+            ..Parameters::default()
+        };
+        for kind in ModelKind::all() {
+            let finishes = Rc::new(RefCell::new(Finishes::default()));
+            let mut model = kind.into_model(params.clone());
+            model.add_observer(Box::new(finishes.clone()));
+            model.run().unwrap();
+            let first = outcome(model.as_ref());
+
+            let mut other = kind.into_model(Parameters {
+                seed: 7,
+                ..params.clone()
+            });
+            other.run().unwrap();
+            model.reset(7).unwrap();
+            model.simulate();
+            assert_eq!(outcome(model.as_ref()), outcome(other.as_ref()));
+            assert_eq!(model.get_params().seed, 7);
+
+            model.reset(params.seed).unwrap();
+            model.simulate();
+            assert_eq!(outcome(model.as_ref()), first);
+            assert_eq!(finishes.borrow().0, 3);
+            // The trace only covers the last run
+            let spans = model.get_trace().unwrap().spans();
+            assert_eq!(spans.iter().filter(|s| s.name == "simulate").count(), 1);
+        }
+    }
+
+    #[test]
+    fn loads_parameters_saved_by_the_first_version() {
+        let json = r#"{"r0": 1.5, "infectious_period": 3.0, "population": 1000,
+            "initial_infections": 5, "seed": 1, "max_time": 100.0,
+            "enable_stats": false, "disable_queries": true}"#;
+        let params: Parameters = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            params,
+            Parameters {
+                population: 1000,
+                seed: 1,
+                ..Parameters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn builder_validates() {
+        let params = Parameters::builder().population(10).build().unwrap();
+        assert_eq!(params.population, 10);
+        assert_eq!(params.r0, Parameters::default().r0);
+
+        assert_eq!(
+            Parameters::builder().r0(0.0).build().unwrap_err(),
+            ParameterError::InvalidR0(0.0)
+        );
+        assert!(matches!(
+            Parameters::builder().infectious_period(f64::NAN).build(),
+            Err(ParameterError::InvalidInfectiousPeriod(_))
+        ));
+        assert_eq!(
+            Parameters::builder()
+                .population(3)
+                .initial_infections(4)
+                .build()
+                .unwrap_err(),
+            ParameterError::TooManyInitialInfections {
+                initial_infections: 4,
+                population: 3
+            }
+        );
+        assert_eq!(
+            Parameters::builder().max_time(-1.0).build().unwrap_err(),
+            ParameterError::InvalidMaxTime(-1.0)
+        );
+    }
 }
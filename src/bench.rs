@@ -0,0 +1,265 @@
+use crate::{
+    ModelKind,
+    alloc::AllocStats,
+    base::{ParameterError, Parameters, SIRModel},
+    manifest::Manifest,
+    time_model, time_reset,
+};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, StudentsT};
+use statrs::statistics::Statistics;
+use std::io;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BenchResult {
+    pub scenario: String,
+    pub model: ModelKind,
+    pub params: Parameters,
+    // Whether one model was reset between runs, in which case the samples
+    // only time the epidemic loop. ixa's reset still builds a new Context, so
+    // only the baseline reuses its storage
+    #[serde(default)]
+    pub reuse: bool,
+    // Wall time in seconds of each timed run
+    pub samples: Vec<f64>,
+    // From the last run; only measured with the `count-allocations` feature
+    #[serde(default)]
+    pub setup_alloc: Option<AllocStats>,
+    #[serde(default)]
+    pub simulate_alloc: Option<AllocStats>,
+    // Of the last run, to check the outcome against; None in older files
+    #[serde(default)]
+    pub cum_incidence: Option<usize>,
+    // Infection attempts and recoveries of the last run
+    #[serde(default)]
+    pub events: Option<usize>,
+}
+
+impl BenchResult {
+    pub fn mean(&self) -> f64 {
+        self.samples.iter().mean()
+    }
+    pub fn std_dev(&self) -> f64 {
+        self.samples.iter().std_dev()
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct BenchFile {
+    // The build and machine that produced the results; None in older files
+    #[serde(default)]
+    pub manifest: Option<Manifest>,
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchFile {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+}
+
+pub fn run_benchmark(
+    scenario: &str,
+    kind: ModelKind,
+    params: &Parameters,
+    warmup: usize,
+    runs: usize,
+    reuse: bool,
+) -> Result<BenchResult, ParameterError> {
+    let mut samples = Vec::with_capacity(runs);
+    let mut setup_alloc = None;
+    let mut simulate_alloc = None;
+    let mut cum_incidence = None;
+    let mut events = None;
+    let mut reused: Option<Box<dyn SIRModel>> = None;
+    for i in 0..warmup + runs {
+        let record = match &mut reused {
+            Some(model) => time_reset(kind, model, params.seed)?,
+            None => {
+                let mut model = kind.into_model(params.clone());
+                let record = time_model(kind, &mut model)?;
+                if reuse {
+                    reused = Some(model);
+                }
+                record
+            }
+        };
+        if i >= warmup {
+            samples.push(if reuse {
+                record.simulate_seconds
+            } else {
+                record.total_seconds
+            });
+        }
+        setup_alloc = record.setup_alloc;
+        simulate_alloc = record.simulate_alloc;
+        cum_incidence = Some(record.cum_incidence);
+        events = Some(record.events.infection_attempts + record.events.recoveries);
+    }
+    Ok(BenchResult {
+        scenario: scenario.to_string(),
+        model: kind,
+        params: params.clone(),
+        reuse,
+        samples,
+        setup_alloc,
+        simulate_alloc,
+        cum_incidence,
+        events,
+    })
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub scenario: String,
+    pub model: ModelKind,
+    pub before: f64,
+    pub after: f64,
+    // Relative change in mean wall time, e.g. 0.1 is 10% slower
+    pub change: f64,
+    // 95% confidence interval of `change`; needs at least two runs on each side
+    pub interval: Option<(f64, f64)>,
+}
+
+impl Comparison {
+    // A regression is a slowdown beyond the threshold that is also
+    // distinguishable from noise (when there are enough runs to tell).
+    pub fn is_regression(&self, threshold: f64) -> bool {
+        self.change > threshold && self.interval.is_none_or(|(low, _)| low > 0.0)
+    }
+}
+
+pub fn compare(before: &BenchResult, after: &BenchResult) -> Comparison {
+    let (m1, m2) = (before.mean(), after.mean());
+    let (n1, n2) = (before.samples.len() as f64, after.samples.len() as f64);
+
+    // Welch's t-interval for the difference in means, scaled by the old mean
+    let interval = (n1 >= 2.0 && n2 >= 2.0).then(|| {
+        let (v1, v2) = (before.std_dev().powi(2) / n1, after.std_dev().powi(2) / n2);
+        let se = (v1 + v2).sqrt();
+        if se == 0.0 {
+            let change = (m2 - m1) / m1;
+            return (change, change);
+        }
+        let df = (v1 + v2).powi(2) / (v1.powi(2) / (n1 - 1.0) + v2.powi(2) / (n2 - 1.0));
+        let t = StudentsT::new(0.0, 1.0, df).unwrap().inverse_cdf(0.975);
+        ((m2 - m1 - t * se) / m1, (m2 - m1 + t * se) / m1)
+    });
+
+    Comparison {
+        scenario: after.scenario.clone(),
+        model: after.model,
+        before: m1,
+        after: m2,
+        change: (m2 - m1) / m1,
+        interval,
+    }
+}
+
+pub fn print_comparisons(comparisons: &[Comparison], threshold: f64) {
+    println!(
+        "{:<10} {:<16} {:>12} {:>12} {:>9} {:>22}",
+        "scenario", "model", "before (s)", "after (s)", "change", "95% CI"
+    );
+    for c in comparisons {
+        let interval = c
+            .interval
+            .map(|(low, high)| format!("[{:+.1}%, {:+.1}%]", low * 100.0, high * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        let flag = if c.is_regression(threshold) {
+            "  REGRESSION"
+        } else {
+            ""
+        };
+        println!(
+            "{:<10} {:<16} {:>12.4} {:>12.4} {:>+8.1}% {:>22}{}",
+            c.scenario,
+            c.model.name(),
+            c.before,
+            c.after,
+            c.change * 100.0,
+            interval,
+            flag
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use super::*;
+
+    fn result(samples: Vec<f64>) -> BenchResult {
+        BenchResult {
+            scenario: "sir".to_string(),
+            model: ModelKind::Baseline,
+            params: Parameters::default(),
+            reuse: false,
+            samples,
+            setup_alloc: None,
+            simulate_alloc: None,
+            cum_incidence: None,
+            events: None,
+        }
+    }
+
+    #[test]
+    fn detects_regression() {
+        let before = result(vec![1.0, 1.01, 0.99, 1.0]);
+        let after = result(vec![1.5, 1.51, 1.49, 1.5]);
+        let c = compare(&before, &after);
+        assert_relative_eq!(c.change, 0.5);
+        let (low, high) = c.interval.unwrap();
+        assert!(low > 0.4 && high < 0.6);
+        assert!(c.is_regression(0.1));
+        assert!(!c.is_regression(0.6));
+    }
+
+    #[test]
+    fn noise_is_not_a_regression() {
+        let before = result(vec![1.0, 2.0, 1.0, 2.0]);
+        let after = result(vec![2.0, 1.5, 2.0, 1.5]);
+        let c = compare(&before, &after);
+        assert!(c.change > 0.1);
+        assert!(!c.is_regression(0.1));
+    }
+
+    #[test]
+    fn single_run() {
+        let c = compare(&result(vec![1.0]), &result(vec![0.5]));
+        assert!(c.interval.is_none());
+        assert!(!c.is_regression(0.1));
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join("sir-bench-round-trip.json");
+        let file = BenchFile {
+            manifest: Some(Manifest::current().clone()),
+            results: vec![
+                run_benchmark(
+                    "sir",
+                    ModelKind::Baseline,
+                    &Parameters::default(),
+                    0,
+                    2,
+                    false,
+                )
+                .unwrap(),
+            ],
+        };
+        file.save(&path).unwrap();
+        let loaded = BenchFile::load(&path).unwrap();
+        assert_eq!(loaded.results.len(), 1);
+        assert_eq!(loaded.results[0].samples, file.results[0].samples);
+        assert_eq!(loaded.results[0].params.population, 1000);
+        assert_eq!(loaded.manifest.as_ref(), Some(Manifest::current()));
+    }
+}
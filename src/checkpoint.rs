@@ -0,0 +1,303 @@
+use crate::{
+    ModelKind,
+    base::{ParameterError, Parameters},
+    stats::ModelStats,
+    transmission::TransmissionTree,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fmt, io, path::Path};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Susceptible,
+    Infectious,
+    Recovered,
+}
+
+// Everything a model needs to continue a run exactly where it left off. It is
+// taken between events, before the next event times are drawn.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelState {
+    pub params: Parameters,
+    pub time: f64,
+    // The model's random stream, in its generator's own serialization
+    pub rng: Value,
+    // The stream infectors are drawn from when tracking transmission; absent
+    // in older checkpoints, whose runs drew them from `rng`
+    #[serde(default)]
+    pub infector_rng: Option<Value>,
+    // Indexed by person
+    pub statuses: Vec<Status>,
+    // In the order the model samples them from
+    pub infectious: Vec<usize>,
+    // Likewise, for `direct_sampling`; empty in older checkpoints
+    #[serde(default)]
+    pub susceptible: Vec<usize>,
+    pub stats: ModelStats,
+    pub transmission_tree: Option<TransmissionTree>,
+}
+
+impl ModelState {
+    // Checks that the state could have come from a run with its parameters
+    pub fn validate(&self) -> Result<(), CheckpointError> {
+        self.params.validate()?;
+        if self.statuses.len() != self.params.population {
+            return Err(CheckpointError::Invalid(format!(
+                "{} statuses for a population of {}",
+                self.statuses.len(),
+                self.params.population
+            )));
+        }
+        let n_infectious = self
+            .statuses
+            .iter()
+            .filter(|&&s| s == Status::Infectious)
+            .count();
+        if n_infectious != self.infectious.len()
+            || self
+                .infectious
+                .iter()
+                .any(|&i| self.statuses.get(i) != Some(&Status::Infectious))
+        {
+            return Err(CheckpointError::Invalid(
+                "the infectious list doesn't match the statuses".to_string(),
+            ));
+        }
+        if self.params.direct_sampling || !self.susceptible.is_empty() {
+            let n_susceptible = self
+                .statuses
+                .iter()
+                .filter(|&&s| s == Status::Susceptible)
+                .count();
+            if n_susceptible != self.susceptible.len()
+                || self
+                    .susceptible
+                    .iter()
+                    .any(|&i| self.statuses.get(i) != Some(&Status::Susceptible))
+            {
+                return Err(CheckpointError::Invalid(
+                    "the susceptible list doesn't match the statuses".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // The susceptible people in the order to sample them from, which is by
+    // index when the checkpoint doesn't say
+    pub fn susceptible_order(&self) -> Vec<usize> {
+        if !self.susceptible.is_empty() {
+            return self.susceptible.clone();
+        }
+        (0..self.statuses.len())
+            .filter(|&i| self.statuses[i] == Status::Susceptible)
+            .collect()
+    }
+}
+
+// Called with the time a checkpoint was requested for and the model's state
+pub type CheckpointHook = Box<dyn FnMut(f64, &ModelState)>;
+
+// Checkpoint times that haven't been reached yet, and what to do with them
+pub struct Checkpoints {
+    // Sorted latest first
+    times: Vec<f64>,
+    hook: CheckpointHook,
+}
+
+impl Checkpoints {
+    pub fn new(mut times: Vec<f64>, hook: CheckpointHook) -> Self {
+        times.sort_by(|a, b| b.total_cmp(a));
+        Self { times, hook }
+    }
+
+    // Whether a checkpoint is due at time `t`
+    pub fn due(&self, t: f64) -> bool {
+        self.times.last().is_some_and(|&next| t >= next)
+    }
+
+    // Passes `state` to the hook once for every checkpoint due at its time
+    pub fn save(&mut self, state: &ModelState) {
+        while self.due(state.time) {
+            let requested = self.times.pop().unwrap();
+            (self.hook)(requested, state);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub model: ModelKind,
+    pub state: ModelState,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+    pub fn save(&self, path: &Path) -> Result<(), CheckpointError> {
+        let file = std::fs::File::create(path)?;
+        Ok(serde_json::to_writer(io::BufWriter::new(file), self)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    // The model can't save its state with these parameters
+    Unsupported(String),
+    Invalid(String),
+    Parameters(ParameterError),
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Unsupported(reason) => write!(f, "can't checkpoint: {reason}"),
+            CheckpointError::Invalid(reason) => write!(f, "invalid checkpoint: {reason}"),
+            CheckpointError::Parameters(err) => write!(f, "invalid checkpoint: {err}"),
+            CheckpointError::Io(err) => write!(f, "{err}"),
+            CheckpointError::Json(err) => write!(f, "invalid checkpoint: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<ParameterError> for CheckpointError {
+    fn from(err: ParameterError) -> Self {
+        CheckpointError::Parameters(err)
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(err: serde_json::Error) -> Self {
+        CheckpointError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn check_resume(kind: ModelKind, params: Parameters) {
+        let mut uninterrupted = kind.into_model(params.clone());
+        uninterrupted.run().unwrap();
+
+        let saved = Rc::new(RefCell::new(Vec::new()));
+        let sink = saved.clone();
+        let mut model = kind.into_model(params);
+        model
+            .set_checkpoints(Checkpoints::new(
+                vec![20.0, 10.0],
+                Box::new(move |requested, state| {
+                    assert!(state.time >= requested);
+                    sink.borrow_mut().push(state.clone());
+                }),
+            ))
+            .unwrap();
+        model.run().unwrap();
+        let expected = uninterrupted.get_transmission_tree().unwrap().infections();
+        assert_eq!(
+            model.get_transmission_tree().unwrap().infections(),
+            expected
+        );
+
+        let states = saved.take();
+        assert_eq!(states.len(), 2);
+        for state in states {
+            let json = serde_json::to_string(&Checkpoint { model: kind, state }).unwrap();
+            let checkpoint: Checkpoint = serde_json::from_str(&json).unwrap();
+            let mut resumed = kind.restore(checkpoint.state).unwrap();
+            resumed.simulate();
+
+            assert_eq!(resumed.current_time(), uninterrupted.current_time());
+            assert_eq!(
+                resumed.get_transmission_tree().unwrap().infections(),
+                expected
+            );
+            let (a, b) = (uninterrupted.get_stats(), resumed.get_stats());
+            assert_eq!(a.get_events(), b.get_events());
+            assert_eq!(a.get_cum_incidence(), b.get_cum_incidence());
+            assert_eq!(a.get_peak_prevalence(), b.get_peak_prevalence());
+        }
+    }
+
+    fn params() -> Parameters {
+        Parameters {
+            track_transmission: true,
+            common_random_numbers: true,
+            ..Parameters::default()
+        }
+    }
+
+    #[test]
+    fn baseline_resumes() {
+        check_resume(ModelKind::Baseline, params());
+        check_resume(
+            ModelKind::Baseline,
+            Parameters {
+                common_random_numbers: false,
+                rng: crate::rng::RngKind::Chacha8,
+                ..params()
+            },
+        );
+        for store in crate::sir::store::StoreKind::all() {
+            check_resume(ModelKind::Baseline, Parameters { store, ..params() });
+            check_resume(
+                ModelKind::Baseline,
+                Parameters {
+                    store,
+                    direct_sampling: true,
+                    ..params()
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn ixa_resumes() {
+        check_resume(ModelKind::Ixa, params());
+        check_resume(ModelKind::IxaNoQueries, params());
+        check_resume(ModelKind::IxaInfectedSet, params());
+        check_resume(
+            ModelKind::Ixa,
+            Parameters {
+                direct_sampling: true,
+                ..params()
+            },
+        );
+    }
+
+    #[test]
+    fn ixa_needs_common_random_numbers() {
+        let mut model = ModelKind::Ixa.into_model(Parameters::default());
+        let checkpoints = Checkpoints::new(vec![1.0], Box::new(|_, _| {}));
+        assert!(matches!(
+            model.set_checkpoints(checkpoints),
+            Err(CheckpointError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_inconsistent_state() {
+        let mut model = ModelKind::Baseline.into_model(Parameters::default());
+        model.setup().unwrap();
+        let mut state = model.checkpoint().unwrap();
+        state.infectious.pop();
+        assert!(matches!(
+            ModelKind::Baseline.restore(state),
+            Err(CheckpointError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    ModelKind,
+    base::{ParameterError, Parameters},
+    observer::SimulationObserver,
+    scenario::Scenario,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+// FNV-1a, which unlike std's hashers is the same in every release
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// Hashes the sequence of events of a run. Times are rounded to microseconds
+// so that the last bits of `ln` on another platform don't change it.
+pub struct Fingerprint {
+    hash: u64,
+    events: usize,
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self {
+            hash: FNV_OFFSET,
+            events: 0,
+        }
+    }
+}
+
+impl Fingerprint {
+    fn write(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.hash ^= u64::from(byte);
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    fn event(&mut self, kind: u64, t: f64, person: usize, other: Option<usize>) {
+        self.events += 1;
+        self.write(kind);
+        self.write((t * 1e6).round() as u64);
+        self.write(person as u64);
+        self.write(other.map_or(u64::MAX, |p| p as u64));
+    }
+    pub fn hash(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+    pub fn events(&self) -> usize {
+        self.events
+    }
+}
+
+impl SimulationObserver for Fingerprint {
+    fn on_infection(&mut self, t: f64, person: usize, infector: Option<usize>) {
+        self.event(0, t, person, infector);
+    }
+    fn on_recovery(&mut self, t: f64, person: usize) {
+        self.event(1, t, person, None);
+    }
+}
+
+// The outcome of one run of a scenario, as committed in the golden files
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GoldenRun {
+    pub model: ModelKind,
+    pub common_random_numbers: bool,
+    pub fingerprint: String,
+    pub events: usize,
+    // Rounded like the event times
+    pub final_time: f64,
+    pub cum_incidence: usize,
+    pub peak_prevalence: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GoldenFile {
+    pub scenario: Scenario,
+    pub params: Parameters,
+    pub runs: Vec<GoldenRun>,
+}
+
+impl GoldenFile {
+    // Runs every model on the scenario, with and without common random numbers
+    pub fn generate(scenario: Scenario) -> Result<Self, ParameterError> {
+        let params = scenario.params();
+        let mut runs = Vec::new();
+        for common_random_numbers in [false, true] {
+            for model in ModelKind::all() {
+                runs.push(golden_run(
+                    model,
+                    Parameters {
+                        common_random_numbers,
+                        ..params.clone()
+                    },
+                )?);
+            }
+        }
+        Ok(Self {
+            scenario,
+            params,
+            runs,
+        })
+    }
+    // Where the golden file of a scenario is committed
+    pub fn path(scenario: Scenario) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("golden")
+            .join(format!("{}.json", scenario.name()))
+    }
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        std::fs::write(path, json)
+    }
+
+    // Describes each difference from the expected file
+    pub fn differences(&self, expected: &GoldenFile) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.params != expected.params {
+            differences.push(format!(
+                "parameters changed from {:?} to {:?}",
+                expected.params, self.params
+            ));
+        }
+        for run in &self.runs {
+            let label = format!(
+                "{}{}",
+                run.model.name(),
+                if run.common_random_numbers {
+                    " with common random numbers"
+                } else {
+                    ""
+                }
+            );
+            match expected.runs.iter().find(|e| {
+                e.model == run.model && e.common_random_numbers == run.common_random_numbers
+            }) {
+                Some(e) if e == run => {}
+                Some(e) => differences.push(format!(
+                    "{label}: fingerprint {} -> {}, {} -> {} events, final time {} -> {}, \
+                     incidence {} -> {}",
+                    e.fingerprint,
+                    run.fingerprint,
+                    e.events,
+                    run.events,
+                    e.final_time,
+                    run.final_time,
+                    e.cum_incidence,
+                    run.cum_incidence
+                )),
+                None => differences.push(format!("{label}: no golden run")),
+            }
+        }
+        differences
+    }
+}
+
+fn golden_run(model: ModelKind, params: Parameters) -> Result<GoldenRun, ParameterError> {
+    let common_random_numbers = params.common_random_numbers;
+    let fingerprint = Rc::new(RefCell::new(Fingerprint::default()));
+    let mut sir = model.into_model(params);
+    sir.add_observer(Box::new(fingerprint.clone()));
+    sir.run()?;
+    let fingerprint = fingerprint.borrow();
+    let stats = sir.get_stats();
+    Ok(GoldenRun {
+        model,
+        common_random_numbers,
+        fingerprint: fingerprint.hash(),
+        events: fingerprint.events(),
+        final_time: (sir.current_time() * 1e6).round() / 1e6,
+        cum_incidence: stats.get_cum_incidence(),
+        peak_prevalence: stats.get_peak_prevalence(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_golden(scenario: Scenario) {
+        let path = GoldenFile::path(scenario);
+        let expected = GoldenFile::load(&path).unwrap();
+        let differences = GoldenFile::generate(scenario)
+            .unwrap()
+            .differences(&expected);
+        assert!(
+            differences.is_empty(),
+            "{} no longer matches {}; if the change is intended, run \
+             `cargo run --release --bin {} -- golden --update`:\n{}",
+            scenario.name(),
+            path.display(),
+            scenario.name(),
+            differences.join("\n")
+        );
+    }
+
+    #[test]
+    fn sir_matches_golden() {
+        check_golden(Scenario::Sir);
+    }
+
+    // Slow without optimizations: cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn large_matches_golden() {
+        check_golden(Scenario::Large);
+    }
+
+    #[test]
+    fn fingerprints_see_every_event() {
+        let params = Scenario::Sir.params();
+        let run = golden_run(ModelKind::Baseline, params.clone()).unwrap();
+        assert_eq!(
+            run,
+            golden_run(ModelKind::Baseline, params.clone()).unwrap()
+        );
+        let other = golden_run(
+            ModelKind::Baseline,
+            Parameters {
+                seed: params.seed + 1,
+                ..params
+            },
+        )
+        .unwrap();
+        assert_ne!(run.fingerprint, other.fingerprint);
+        assert_eq!(run.events, run.cum_incidence * 2 + 5);
+    }
+}
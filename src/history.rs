@@ -0,0 +1,289 @@
+use crate::{ModelKind, bench::BenchResult, manifest::Manifest};
+use plotly::{
+    Layout, Plot, Scatter,
+    common::{ErrorData, ErrorType, Mode, Title},
+    layout::{Axis, AxisType},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{self, BufRead, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Where `bench` and `compare` append their results unless told otherwise
+pub const DEFAULT_PATH: &str = "bench-history.jsonl";
+
+// One line of the history file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub manifest: Manifest,
+    #[serde(flatten)]
+    pub result: BenchResult,
+}
+
+impl HistoryEntry {
+    // A result of this build on this machine, timestamped now
+    pub fn new(result: BenchResult) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            manifest: Manifest::current().clone(),
+            result,
+        }
+    }
+}
+
+pub fn append(path: &Path, entries: &[HistoryEntry]) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for entry in entries {
+        serde_json::to_writer(&mut file, entry)?;
+        writeln!(file)?;
+    }
+    Ok(())
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let file = std::fs::File::open(path)?;
+    let mut entries = Vec::new();
+    for (i, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {err}", i + 1))
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// The results of one scenario and model over time. Runs with `reuse` only
+// time the epidemic loop, so they get a trend of their own.
+#[derive(Debug)]
+pub struct Trend {
+    pub scenario: String,
+    pub model: ModelKind,
+    pub reuse: bool,
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl Trend {
+    pub fn label(&self) -> String {
+        format!(
+            "{} {}{}",
+            self.scenario,
+            self.model.name(),
+            if self.reuse { " (reuse)" } else { "" }
+        )
+    }
+    // Relative change in mean wall time from the first entry to the last
+    pub fn change(&self) -> f64 {
+        let first = self.entries[0].result.mean();
+        (self.entries[self.entries.len() - 1].result.mean() - first) / first
+    }
+}
+
+// Groups the entries by scenario and model, in the order each first
+// appears, with each group sorted by time
+pub fn trends(entries: &[HistoryEntry]) -> Vec<Trend> {
+    let mut trends: Vec<Trend> = Vec::new();
+    for entry in entries {
+        let result = &entry.result;
+        match trends.iter_mut().find(|t| {
+            t.scenario == result.scenario && t.model == result.model && t.reuse == result.reuse
+        }) {
+            Some(trend) => trend.entries.push(entry.clone()),
+            None => trends.push(Trend {
+                scenario: result.scenario.clone(),
+                model: result.model,
+                reuse: result.reuse,
+                entries: vec![entry.clone()],
+            }),
+        }
+    }
+    for trend in &mut trends {
+        trend.entries.sort_by_key(|e| e.timestamp);
+    }
+    trends
+}
+
+pub fn print_trends(trends: &[Trend]) {
+    for trend in trends {
+        println!("{}", trend.label());
+        println!(
+            "  {:<19} {:<16} {:<8} {:<12} {:>10} {:>10} {:>9}",
+            "date", "commit", "ixa", "host", "mean (s)", "std (s)", "change"
+        );
+        let mut previous: Option<f64> = None;
+        for entry in &trend.entries {
+            let mean = entry.result.mean();
+            let change = previous
+                .map(|p| format!("{:+.1}%", (mean - p) / p * 100.0))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "  {:<19} {:<16} {:<8} {:<12} {:>10.4} {:>10.4} {:>9}",
+                format_timestamp(entry.timestamp),
+                entry.manifest.commit,
+                entry.manifest.ixa_version,
+                entry.manifest.host.hostname,
+                mean,
+                entry.result.std_dev(),
+                change
+            );
+            previous = Some(mean);
+        }
+        if trend.entries.len() > 1 {
+            println!(
+                "  {:+.1}% over {} runs",
+                trend.change() * 100.0,
+                trend.entries.len()
+            );
+        }
+        println!();
+    }
+}
+
+// Mean wall time of each trend against the date, with the standard
+// deviation as error bars. The scenarios differ by orders of magnitude, so
+// the time axis is logarithmic.
+pub fn plot(trends: &[Trend]) -> Plot {
+    let mut plot = Plot::new();
+    for trend in trends {
+        let dates = trend
+            .entries
+            .iter()
+            .map(|e| format_timestamp(e.timestamp))
+            .collect();
+        let means = trend.entries.iter().map(|e| e.result.mean()).collect();
+        let std_devs = trend
+            .entries
+            .iter()
+            .map(|e| {
+                if e.result.samples.len() > 1 {
+                    e.result.std_dev()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let hover = trend
+            .entries
+            .iter()
+            .map(|e| {
+                format!(
+                    "{} (ixa {}, {}) on {}",
+                    e.manifest.commit,
+                    e.manifest.ixa_version,
+                    e.manifest.rustc,
+                    e.manifest.host.hostname
+                )
+            })
+            .collect();
+        plot.add_trace(
+            Scatter::new(dates, means)
+                .name(trend.label())
+                .mode(Mode::LinesMarkers)
+                .error_y(ErrorData::new(ErrorType::Data).array(std_devs))
+                .hover_text_array(hover),
+        );
+    }
+    plot.set_layout(
+        Layout::new()
+            .title(Title::from("Benchmark history"))
+            .x_axis(Axis::new().type_(AxisType::Date))
+            .y_axis(
+                Axis::new()
+                    .title(Title::from("mean wall time (s)"))
+                    .type_(AxisType::Log),
+            ),
+    );
+    plot
+}
+
+// UTC, in a format plotly reads as a date
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    // Howard Hinnant's days_from_civil, inverted
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::Parameters;
+
+    fn entry(timestamp: u64, scenario: &str, model: ModelKind, mean: f64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            ..HistoryEntry::new(BenchResult {
+                scenario: scenario.to_string(),
+                model,
+                params: Parameters::default(),
+                reuse: false,
+                samples: vec![mean * 0.9, mean * 1.1],
+                setup_alloc: None,
+                simulate_alloc: None,
+                cum_incidence: None,
+                events: None,
+            })
+        }
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1_792_405_845), "2026-10-19 10:30:45");
+    }
+
+    #[test]
+    fn appends_and_groups() {
+        let path = std::env::temp_dir().join("sir-bench-history.jsonl");
+        let _ = std::fs::remove_file(&path);
+        append(
+            &path,
+            &[
+                entry(200, "sir", ModelKind::Ixa, 2.0),
+                entry(100, "sir", ModelKind::Baseline, 1.0),
+            ],
+        )
+        .unwrap();
+        append(&path, &[entry(300, "sir", ModelKind::Baseline, 1.5)]).unwrap();
+        let entries = load(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].result.samples, vec![1.8, 2.2]);
+        assert_eq!(&entries[0].manifest, Manifest::current());
+
+        let trends = trends(&entries);
+        assert_eq!(trends.len(), 2);
+        assert_eq!(trends[0].label(), "sir ixa");
+        assert_eq!(trends[1].entries.len(), 2);
+        assert!((trends[1].change() - 0.5).abs() < 1e-12);
+
+        let html = plot(&trends).to_html();
+        assert!(html.contains("sir baseline"));
+        assert!(html.contains("1970-01-01 00:05:00"));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::stats::ModelStats;
+use std::fmt;
+
+// An event the invariants are checked after, with the person it happened to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckedEvent {
+    Infection(usize),
+    Recovery(usize),
+    // A contact with someone who wasn't susceptible
+    RejectedContact(usize),
+}
+
+impl fmt::Display for CheckedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckedEvent::Infection(p) => write!(f, "infection of person {p}"),
+            CheckedEvent::Recovery(p) => write!(f, "recovery of person {p}"),
+            CheckedEvent::RejectedContact(p) => write!(f, "rejected contact with person {p}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvariantViolation {
+    // Number of the event, counting from 1 since the checks started
+    pub event: usize,
+    pub time: f64,
+    pub after: CheckedEvent,
+    pub message: String,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invariant violated after event {} ({} at t={}): {}",
+            self.event, self.after, self.time, self.message
+        )
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+// Checks a model's state after each event for `check_invariants`. The models
+// call `event` and then `ensure` for each of their invariants, so that a
+// violation reports the event that caused it.
+#[derive(Clone, Debug)]
+pub struct InvariantChecker {
+    events: usize,
+    time: f64,
+    last: Option<CheckedEvent>,
+}
+
+impl InvariantChecker {
+    // `time` is the model's time when the checks start
+    pub fn new(time: f64) -> Self {
+        Self {
+            events: 0,
+            time,
+            last: None,
+        }
+    }
+
+    // Starts checking the state after `event` at time `t`
+    pub fn event(&mut self, t: f64, event: CheckedEvent) -> Result<(), InvariantViolation> {
+        let previous = self.time;
+        self.events += 1;
+        self.time = t;
+        self.last = Some(event);
+        self.ensure(t >= previous, || {
+            format!("time went backwards from {previous} to {t}")
+        })
+    }
+
+    // Fails with the context of the current event unless `ok`
+    pub fn ensure<F>(&self, ok: bool, message: F) -> Result<(), InvariantViolation>
+    where
+        F: FnOnce() -> String,
+    {
+        if ok {
+            return Ok(());
+        }
+        Err(InvariantViolation {
+            event: self.events,
+            time: self.time,
+            after: self.last.expect("checked after an event"),
+            message: message(),
+        })
+    }
+
+    // The invariants of the counts every model keeps: prevalence is the
+    // number of infectious people, and everyone who isn't susceptible was
+    // either seeded or infected. `recovered` is None where it can't be
+    // counted cheaply.
+    pub fn check_stats(
+        &self,
+        stats: &ModelStats,
+        initial_infections: usize,
+        infectious: usize,
+        recovered: Option<usize>,
+    ) -> Result<(), InvariantViolation> {
+        self.ensure(stats.get_prevalence() == infectious, || {
+            format!(
+                "prevalence is {} but {infectious} people are infectious",
+                stats.get_prevalence()
+            )
+        })?;
+        if let Some(recovered) = recovered {
+            let infected = stats.get_cum_incidence() + initial_infections;
+            self.ensure(infected == infectious + recovered, || {
+                format!(
+                    "{infected} people were seeded or infected but {infectious} are infectious \
+                     and {recovered} recovered"
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters, stop::StopReason};
+
+    #[test]
+    fn models_keep_their_invariants() {
+        for common_random_numbers in [false, true] {
+            let params = Parameters {
+                population: 2000,
+                check_invariants: true,
+                common_random_numbers,
+                ..Parameters::default()
+            };
+            for kind in ModelKind::all() {
+                let mut model = kind.into_model(params.clone());
+                model.run().unwrap();
+                assert_eq!(model.invariant_violation(), None, "{kind:?}");
+                assert_ne!(model.stop_reason(), Some(StopReason::Invariant));
+            }
+        }
+    }
+
+    #[test]
+    fn reports_the_event() {
+        let mut checker = InvariantChecker::new(0.0);
+        checker.event(1.0, CheckedEvent::Infection(3)).unwrap();
+        checker.event(2.0, CheckedEvent::Recovery(3)).unwrap();
+        let err = checker
+            .event(1.5, CheckedEvent::RejectedContact(4))
+            .unwrap_err();
+        assert_eq!(err.event, 3);
+        assert_eq!(
+            err.to_string(),
+            "invariant violated after event 3 (rejected contact with person 4 at t=1.5): \
+             time went backwards from 2 to 1.5"
+        );
+
+        let mut stats = ModelStats::new(2);
+        stats.record_infection();
+        assert!(checker.check_stats(&stats, 2, 3, Some(0)).is_ok());
+        assert!(checker.check_stats(&stats, 2, 2, Some(1)).is_err());
+        assert!(checker.check_stats(&stats, 2, 3, Some(1)).is_err());
+    }
+}
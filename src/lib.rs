@@ -1,30 +1,102 @@
+pub mod alloc;
 pub mod base;
+pub mod bench;
+pub mod checkpoint;
+pub mod fingerprint;
+pub mod history;
+pub mod invariants;
+pub mod manifest;
+pub mod observer;
+pub mod output;
+pub mod parallel;
+pub mod progress;
+pub mod reference;
+pub mod report;
+pub mod rng;
+pub mod scaling;
+pub mod scenario;
 pub mod sir;
 pub mod stats;
+pub mod stop;
+pub mod trace;
+pub mod transmission;
 
-use base::{Parameters, SIRModel};
-use clap::{Parser, ValueEnum};
-use sir::{baseline, ixa};
+use alloc::{AllocPhase, AllocStats};
+use base::{ParameterError, Parameters, SIRModel};
+use bench::{BenchFile, compare, print_comparisons, run_benchmark};
+use checkpoint::{Checkpoint, CheckpointError, Checkpoints, ModelState};
+use clap::{Parser, Subcommand, ValueEnum};
+use fingerprint::GoldenFile;
+use history::HistoryEntry;
+use invariants::InvariantViolation;
+use manifest::Manifest;
+use observer::NoopObserver;
+use output::{OutputFormat, RecordWriter, RunRecord};
+use parallel::{Job, replicate_jobs, run_jobs};
+use progress::ProgressReporter;
+use rng::RngKind;
+use scenario::Scenario;
+use serde::{Deserialize, Serialize};
+use sir::{baseline, ixa, store::StoreKind};
+use stats::ModelStats;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use stop::StopConditions;
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static GLOBAL: alloc::CountingAllocator = alloc::CountingAllocator;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ModelKind {
     Baseline,
     Ixa,
     IxaNoQueries,
+    // ixa-no-queries with the infectious people in an IndexSet
+    IxaInfectedSet,
 }
 
 impl ModelKind {
     pub fn all() -> Vec<Self> {
-        vec![Self::Baseline, Self::Ixa, Self::IxaNoQueries]
+        vec![
+            Self::Baseline,
+            Self::Ixa,
+            Self::IxaNoQueries,
+            Self::IxaInfectedSet,
+        ]
+    }
+    // The name used on the command line, e.g. "ixa-no-queries"
+    pub fn name(self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
     }
     pub fn into_model(self, params: Parameters) -> Box<dyn SIRModel> {
-        match self {
-            ModelKind::Baseline => Box::new(baseline::Context::new(params)),
+        let noop_observers = params.noop_observers;
+        let mut model: Box<dyn SIRModel> = match self {
+            ModelKind::Baseline => baseline::new_model(params),
             ModelKind::Ixa => Box::new(ixa::Model::new(params)),
             ModelKind::IxaNoQueries => Box::new(ixa::Model::new(Parameters {
                 disable_queries: true,
                 ..params
             })),
+            ModelKind::IxaInfectedSet => Box::new(ixa::Model::new(Parameters {
+                disable_queries: true,
+                infected_set: true,
+                ..params
+            })),
+        };
+        for _ in 0..noop_observers {
+            model.add_observer(Box::new(NoopObserver));
+        }
+        model
+    }
+    // A model that continues from `state` when simulated, without setup
+    pub fn restore(self, state: ModelState) -> Result<Box<dyn SIRModel>, CheckpointError> {
+        match self {
+            ModelKind::Baseline => baseline::restore_model(state),
+            ModelKind::Ixa | ModelKind::IxaNoQueries | ModelKind::IxaInfectedSet => {
+                Ok(Box::new(ixa::Model::restore(state)?))
+            }
         }
     }
 }
@@ -32,6 +104,9 @@ impl ModelKind {
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(short, long, value_enum)]
     pub model: Option<ModelKind>,
 
@@ -46,26 +121,402 @@ pub struct Args {
 
     #[arg(long)]
     pub disable_queries: bool,
+
+    /// Write the transmission tree of each model to <PREFIX>-<model>.csv and .nwk.
+    /// Infectors come from their own stream, so the runs are the same as without
+    #[arg(long, value_name = "PREFIX")]
+    pub transmission_tree: Option<String>,
+
+    /// Print one machine-readable record per model run instead of status lines
+    #[arg(long, value_enum)]
+    pub output: Option<OutputFormat>,
+
+    /// Measure the time spent sampling, infecting and recovering
+    #[arg(long)]
+    pub profile_events: bool,
+
+    /// Make every model consume the same random numbers for the same decisions
+    #[arg(long)]
+    pub common_random_numbers: bool,
+
+    /// Random number generator for the baseline (and ixa with --common-random-numbers)
+    #[arg(long, value_enum, default_value_t = RngKind::Small)]
+    pub rng: RngKind,
+
+    /// How the baseline stores its people and their statuses
+    #[arg(long, value_enum, default_value_t = StoreKind::IndexSet)]
+    pub store: StoreKind,
+
+    /// Pick infected contacts among the susceptible people at the rate they
+    /// are reached, instead of rejecting contacts who aren't susceptible
+    #[arg(long)]
+    pub direct_sampling: bool,
+
+    /// Save the state of each model to <PREFIX>-<model>-t<TIME>.json at the
+    /// first event at or after each --checkpoint-at time. The ixa models can
+    /// only save their state with --common-random-numbers
+    #[arg(long, value_name = "PREFIX", requires = "checkpoint_at")]
+    pub checkpoint: Option<String>,
+
+    #[arg(long, value_delimiter = ',', requires = "checkpoint")]
+    pub checkpoint_at: Vec<f64>,
+
+    /// Continue the run saved in a checkpoint file instead of starting one
+    #[arg(long, value_name = "FILE")]
+    pub resume: Option<PathBuf>,
+
+    /// Notify this many observers that do nothing of every event
+    #[arg(long, default_value_t = 0)]
+    pub noop_observers: usize,
+
+    /// Print the simulated time, prevalence, incidence and event rate of each
+    /// run to stderr every SECONDS (default 1)
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "1")]
+    pub progress: Option<f64>,
+
+    /// Stop each run after this many seconds of simulation
+    #[arg(long, value_name = "SECONDS")]
+    pub max_wall_seconds: Option<f64>,
+
+    /// Stop each run after this many infection attempts and recoveries
+    #[arg(long)]
+    pub max_events: Option<usize>,
+
+    /// Stop each run once this many people have been infected
+    #[arg(long)]
+    pub max_incidence: Option<usize>,
+
+    /// Stop each run once prevalence falls below this after reaching it
+    #[arg(long)]
+    pub min_prevalence: Option<usize>,
+
+    /// Check each model's state after every event and stop at the first
+    /// inconsistency
+    #[arg(long)]
+    pub check_invariants: bool,
+
+    /// Write a Chrome trace of each model's setup, events, queries and
+    /// reports to <PREFIX>-<model>.json
+    #[arg(long, value_name = "PREFIX")]
+    pub trace: Option<String>,
+
+    /// Runs of each model, with seeds counting up from the scenario's
+    #[arg(long, default_value_t = 1)]
+    pub replicates: usize,
+
+    /// Runs to do at once, or 0 for one per core. Timings are only comparable
+    /// between runs made with 1.
+    #[arg(long, default_value_t = 1)]
+    pub threads: usize,
+}
+
+impl Args {
+    // The scenario's parameters with the options that change them
+    pub fn params(&self, scenario: Scenario) -> Result<Parameters, ParameterError> {
+        scenario
+            .builder()
+            .max_time(self.time)
+            .enable_stats(self.stats)
+            .disable_queries(self.disable_queries)
+            .track_transmission(self.transmission_tree.is_some())
+            .profile_events(self.profile_events)
+            .common_random_numbers(self.common_random_numbers)
+            .rng(self.rng)
+            .store(self.store)
+            .direct_sampling(self.direct_sampling)
+            .noop_observers(self.noop_observers)
+            .stop(self.stop_conditions())
+            .check_invariants(self.check_invariants)
+            .trace(self.trace.is_some())
+            .build()
+    }
+    pub fn stop_conditions(&self) -> StopConditions {
+        StopConditions {
+            max_wall_seconds: self.max_wall_seconds,
+            max_events: self.max_events,
+            max_incidence: self.max_incidence,
+            min_prevalence: self.min_prevalence,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Time repeated runs of each model, optionally saving the results
+    Bench {
+        #[command(flatten)]
+        runs: BenchRuns,
+
+        #[arg(long)]
+        save: Option<PathBuf>,
+    },
+    /// Re-run the scenarios in a saved benchmark file and report the change
+    Compare {
+        #[command(flatten)]
+        runs: BenchRuns,
+
+        #[arg(long)]
+        against: PathBuf,
+
+        /// Relative slowdown that fails the comparison, e.g. 0.1 for 10%
+        #[arg(long, default_value_t = 0.1)]
+        threshold: f64,
+    },
+    /// Print how the timings in a benchmark history file changed over time
+    History {
+        #[arg(long, value_name = "FILE", default_value = history::DEFAULT_PATH)]
+        file: PathBuf,
+
+        /// Also plot the trends to this HTML file
+        #[arg(long, value_name = "FILE")]
+        html: Option<PathBuf>,
+    },
+    /// Benchmark the standard scenarios and write a Markdown report of the
+    /// results
+    Report {
+        #[command(flatten)]
+        runs: BenchRuns,
+
+        /// Scenarios to run (default all)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        scenario: Vec<Scenario>,
+
+        /// Report the latest results in this history file instead of running
+        #[arg(long, value_name = "FILE")]
+        from_history: Option<PathBuf>,
+
+        /// Write the report here instead of to stdout
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Time the epidemic loop of each model over a range of populations and
+    /// estimate how its runtime and cost per event grow with the population
+    Scaling {
+        #[arg(long, default_value_t = 1000)]
+        from: usize,
+
+        #[arg(long, default_value_t = 100_000)]
+        to: usize,
+
+        /// Populations to time, spaced evenly on a log scale
+        #[arg(long, default_value_t = 5)]
+        points: usize,
+
+        #[arg(long, default_value_t = 3)]
+        runs: usize,
+
+        #[arg(long, default_value_t = 1)]
+        warmup: usize,
+
+        /// Exponent of the cost per event above which a model is flagged as
+        /// super-linear
+        #[arg(long, default_value_t = 0.1)]
+        margin: f64,
+    },
+    /// Check the scenario's runs against its committed golden fingerprints
+    Golden {
+        /// Rewrite the golden file with the current runs instead
+        #[arg(long)]
+        update: bool,
+    },
+    /// Time the random draws for one event with each generator
+    RngBench {
+        #[arg(long, default_value_t = 10_000_000)]
+        draws: usize,
+    },
+}
+
+#[derive(clap::Args)]
+pub struct BenchRuns {
+    #[arg(long, default_value_t = 5)]
+    pub runs: usize,
+
+    #[arg(long, default_value_t = 1)]
+    pub warmup: usize,
+
+    /// Reset one model between runs and time only the epidemic loop. ixa
+    /// can't execute a Context twice, so it still builds a new one each run
+    #[arg(long)]
+    pub reuse: bool,
+
+    /// Append the results, with the commit, ixa version and host, to this file
+    #[arg(long, value_name = "FILE", default_value = history::DEFAULT_PATH)]
+    pub history: PathBuf,
+
+    #[arg(long)]
+    pub no_history: bool,
 }
 
-pub fn run_model(kind: ModelKind, model: &mut Box<dyn SIRModel>) {
+impl BenchRuns {
+    fn record_history(&self, results: &[bench::BenchResult]) {
+        if self.no_history {
+            return;
+        }
+        let entries: Vec<_> = results.iter().cloned().map(HistoryEntry::new).collect();
+        history::append(&self.history, &entries).unwrap_or_else(|err| {
+            exit_with_error(format!("can't write {}: {err}", self.history.display()))
+        });
+    }
+}
+
+pub fn run_model(
+    kind: ModelKind,
+    model: &mut Box<dyn SIRModel>,
+) -> Result<RunRecord, ParameterError> {
+    println!("Build: {}", Manifest::current());
     println!(
         "Running model '{:?}' with params {:?}",
         kind,
         model.get_params()
     );
-    model.run();
+    let record = time_model(kind, model)?;
+    print_record(&record);
+    Ok(record)
+}
+
+fn print_record(record: &RunRecord) {
     println!(
         "Completed at time {:.2}, Infection incidence: {}",
-        model.current_time(),
-        model.get_stats().get_cum_incidence()
+        record.final_time, record.cum_incidence
+    );
+    if record.stop_reason.is_limit() {
+        println!("Stopped early: {} limit reached", record.stop_reason);
+    }
+    let events = &record.events;
+    println!(
+        "Events: {} infection attempts, {} infections, {} rejected contacts, {} recoveries, \
+         {} plans scheduled, {} queries",
+        events.infection_attempts,
+        events.infections,
+        events.rejected_contacts,
+        events.recoveries,
+        events.plans_scheduled,
+        events.queries
     );
+    if let Some(times) = &record.event_times {
+        let other = record.simulate_seconds
+            - times.sampling_seconds
+            - times.infection_seconds
+            - times.recovery_seconds;
+        println!(
+            "Event times: sampling {:.4}s, infection {:.4}s, recovery {:.4}s, other {:.4}s",
+            times.sampling_seconds, times.infection_seconds, times.recovery_seconds, other
+        );
+    }
+}
+
+// Runs the model, timing setup and the epidemic loop separately
+pub fn time_model(
+    kind: ModelKind,
+    model: &mut Box<dyn SIRModel>,
+) -> Result<RunRecord, ParameterError> {
+    time_phases(kind, model, |model| model.setup())
+}
+
+// Runs a model that has already been simulated again with a new seed,
+// timing the reset as its setup
+pub fn time_reset(
+    kind: ModelKind,
+    model: &mut Box<dyn SIRModel>,
+    seed: u64,
+) -> Result<RunRecord, ParameterError> {
+    time_phases(kind, model, |model| model.reset(seed))
+}
+
+fn time_phases<F>(
+    kind: ModelKind,
+    model: &mut Box<dyn SIRModel>,
+    setup: F,
+) -> Result<RunRecord, ParameterError>
+where
+    F: FnOnce(&mut Box<dyn SIRModel>) -> Result<(), ParameterError>,
+{
+    let start = Instant::now();
+    let phase = AllocPhase::start();
+    setup(model)?;
+    let setup_seconds = start.elapsed().as_secs_f64();
+    let setup_alloc = phase.finish();
+    Ok(time_simulation(
+        kind,
+        model,
+        start,
+        setup_seconds,
+        setup_alloc,
+    ))
+}
+
+// Runs and times the epidemic loop of a model that has been set up (or
+// restored) since `start`
+fn time_simulation(
+    kind: ModelKind,
+    model: &mut Box<dyn SIRModel>,
+    start: Instant,
+    setup_seconds: f64,
+    setup_alloc: Option<AllocStats>,
+) -> RunRecord {
+    let phase = AllocPhase::start();
+    model.simulate();
+    let total_seconds = start.elapsed().as_secs_f64();
+    let simulate_alloc = phase.finish();
+
+    let stats = model.get_stats();
+    RunRecord {
+        model: kind,
+        replicate: 0,
+        threads: 1,
+        params: model.get_params().clone(),
+        setup_seconds,
+        simulate_seconds: total_seconds - setup_seconds,
+        total_seconds,
+        final_time: model.current_time(),
+        stop_reason: model.stop_reason().expect("the model has been simulated"),
+        cum_incidence: stats.get_cum_incidence(),
+        peak_prevalence: stats.get_peak_prevalence(),
+        peak_rss_bytes: output::peak_rss_bytes(),
+        setup_alloc,
+        simulate_alloc,
+        events: *stats.get_events(),
+        event_times: stats.get_event_times().copied(),
+        manifest: Manifest::current().clone(),
+    }
+}
+
+// Writes each checkpoint of a `kind` model to a file as soon as it is taken
+fn checkpoint_files(prefix: &str, kind: ModelKind, times: Vec<f64>) -> Checkpoints {
+    let prefix = prefix.to_string();
+    Checkpoints::new(
+        times,
+        Box::new(move |requested, state| {
+            let path = PathBuf::from(format!("{prefix}-t{requested}.json"));
+            let checkpoint = Checkpoint {
+                model: kind,
+                state: state.clone(),
+            };
+            checkpoint
+                .save(&path)
+                .unwrap_or_else(|err| exit_with_error(err));
+            eprintln!(
+                "Saved checkpoint {} at time {:.2}",
+                path.display(),
+                state.time
+            );
+        }),
+    )
+}
+
+// Reports an error on the command line without a panic message
+fn exit_with_error(err: impl std::fmt::Display) -> ! {
+    eprintln!("error: {err}");
+    std::process::exit(2);
 }
 
-pub fn run_from_args<F: FnOnce(&Args) -> Parameters>(build_params: F) {
+pub fn run_from_args(scenario: Scenario) {
     let args = Args::parse();
 
-    let params = build_params(&args);
+    let params = args
+        .params(scenario)
+        .unwrap_or_else(|err| exit_with_error(err));
+    let name = scenario.name();
 
     // By default run all the models
     let mut model_kinds: Vec<ModelKind> = Vec::new();
@@ -75,12 +526,391 @@ pub fn run_from_args<F: FnOnce(&Args) -> Parameters>(build_params: F) {
         model_kinds.extend(ModelKind::all());
     }
 
-    for k in model_kinds {
-        let mut model = k.into_model(params.clone());
-        run_model(k, &mut model);
+    match &args.command {
+        Some(Command::Bench { runs, save }) => {
+            println!("Build: {}", Manifest::current());
+            let mut file = BenchFile {
+                manifest: Some(Manifest::current().clone()),
+                ..BenchFile::default()
+            };
+            for k in model_kinds {
+                let result = run_benchmark(name, k, &params, runs.warmup, runs.runs, runs.reuse)
+                    .unwrap_or_else(|err| exit_with_error(err));
+                print!(
+                    "{name} {:<16} {:.4}s ± {:.4}s",
+                    k.name(),
+                    result.mean(),
+                    result.std_dev()
+                );
+                match result.simulate_alloc {
+                    Some(alloc) => println!(
+                        ", peak heap {} bytes, {} allocations",
+                        alloc.peak_bytes,
+                        alloc.allocations + result.setup_alloc.map_or(0, |a| a.allocations)
+                    ),
+                    None => println!(),
+                }
+                file.results.push(result);
+            }
+            runs.record_history(&file.results);
+            if let Some(path) = save {
+                file.save(path).unwrap_or_else(|err| {
+                    exit_with_error(format!("can't write {}: {err}", path.display()))
+                });
+            }
+            return;
+        }
+        Some(Command::Compare {
+            runs,
+            against,
+            threshold,
+        }) => {
+            // Re-run exactly what was saved rather than what the flags ask for
+            let saved = BenchFile::load(against).unwrap_or_else(|err| {
+                exit_with_error(format!("can't read {}: {err}", against.display()))
+            });
+            // Timings from another build or machine may not be comparable
+            match &saved.manifest {
+                Some(manifest) => {
+                    for difference in manifest.differences(Manifest::current()) {
+                        eprintln!(
+                            "warning: {} differs from this build: {difference}",
+                            against.display()
+                        );
+                    }
+                }
+                None => eprintln!(
+                    "warning: {} has no manifest to check the build against",
+                    against.display()
+                ),
+            }
+            let results: Vec<_> = saved
+                .results
+                .iter()
+                .map(|before| {
+                    run_benchmark(
+                        &before.scenario,
+                        before.model,
+                        &before.params,
+                        runs.warmup,
+                        runs.runs,
+                        before.reuse,
+                    )
+                    .unwrap_or_else(|err| exit_with_error(err))
+                })
+                .collect();
+            runs.record_history(&results);
+            let comparisons: Vec<_> = saved
+                .results
+                .iter()
+                .zip(&results)
+                .map(|(before, after)| compare(before, after))
+                .collect();
+            print_comparisons(&comparisons, *threshold);
+            if comparisons.iter().any(|c| c.is_regression(*threshold)) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::History { file, html }) => {
+            let entries = history::load(file).unwrap_or_else(|err| {
+                exit_with_error(format!("can't read {}: {err}", file.display()))
+            });
+            let trends = history::trends(&entries);
+            history::print_trends(&trends);
+            if let Some(path) = html {
+                std::fs::write(path, history::plot(&trends).to_html())
+                    .unwrap_or_else(|err| exit_with_error(err));
+                println!("Wrote {}", path.display());
+            }
+            return;
+        }
+        Some(Command::Report {
+            runs,
+            scenario: scenarios,
+            from_history,
+            out,
+        }) => {
+            let scenarios = if scenarios.is_empty() {
+                Scenario::all().to_vec()
+            } else {
+                scenarios.clone()
+            };
+            let (entries, source) = match from_history {
+                Some(path) => {
+                    let entries = history::load(path).unwrap_or_else(|err| {
+                        exit_with_error(format!("can't read {}: {err}", path.display()))
+                    });
+                    (entries, report::Source::History(path.clone()))
+                }
+                None => {
+                    // The scenarios as published, without the options that change them
+                    let mut results = Vec::new();
+                    for scenario in &scenarios {
+                        for &k in &model_kinds {
+                            eprintln!("Running {} {}", scenario.name(), k.name());
+                            results.push(
+                                run_benchmark(
+                                    scenario.name(),
+                                    k,
+                                    &scenario.params(),
+                                    runs.warmup,
+                                    runs.runs,
+                                    false,
+                                )
+                                .unwrap_or_else(|err| exit_with_error(err)),
+                            );
+                        }
+                    }
+                    runs.record_history(&results);
+                    let entries = results.into_iter().map(HistoryEntry::new).collect();
+                    let source = report::Source::Ran {
+                        warmup: runs.warmup,
+                        runs: runs.runs,
+                    };
+                    (entries, source)
+                }
+            };
+            let entries: Vec<_> = report::latest(&entries)
+                .into_iter()
+                .filter(|e| {
+                    scenarios.iter().any(|s| s.name() == e.result.scenario)
+                        && model_kinds.contains(&e.result.model)
+                })
+                .collect();
+            if entries.is_empty() {
+                exit_with_error("no results to report");
+            }
+            match out {
+                Some(path) => {
+                    let file =
+                        std::fs::File::create(path).unwrap_or_else(|err| exit_with_error(err));
+                    report::write_markdown(std::io::BufWriter::new(file), &entries, &source)
+                        .unwrap_or_else(|err| exit_with_error(err));
+                    println!("Wrote {}", path.display());
+                }
+                None => report::write_markdown(std::io::stdout(), &entries, &source)
+                    .unwrap_or_else(|err| exit_with_error(err)),
+            }
+            return;
+        }
+        Some(Command::Scaling {
+            from,
+            to,
+            points,
+            runs,
+            warmup,
+            margin,
+        }) => {
+            let populations = scaling::populations(*from, *to, *points).unwrap_or_else(|| {
+                exit_with_error(format!(
+                    "scaling needs 0 < --from < --to and at least 2 --points, got --from {from} --to {to} --points {points}"
+                ))
+            });
+            let results: Vec<_> = model_kinds
+                .iter()
+                .map(|&k| {
+                    eprintln!("Timing {} at populations {populations:?}", k.name());
+                    scaling::measure(k, &params, &populations, *warmup, *runs)
+                        .unwrap_or_else(|err| exit_with_error(err))
+                })
+                .collect();
+            scaling::print_scaling(&results, *margin);
+            return;
+        }
+        Some(Command::Golden { update }) => {
+            let path = GoldenFile::path(scenario);
+            let current = GoldenFile::generate(scenario).unwrap_or_else(|err| exit_with_error(err));
+            if *update {
+                current
+                    .save(&path)
+                    .unwrap_or_else(|err| exit_with_error(err));
+                println!("Wrote {}", path.display());
+                return;
+            }
+            let expected = GoldenFile::load(&path).unwrap_or_else(|err| {
+                exit_with_error(format!("can't read {}: {err}", path.display()))
+            });
+            let differences = current.differences(&expected);
+            if differences.is_empty() {
+                println!("{} matches {}", scenario.name(), path.display());
+                return;
+            }
+            for difference in differences {
+                println!("{difference}");
+            }
+            std::process::exit(1);
+        }
+        Some(Command::RngBench { draws }) => {
+            for rng in RngKind::all() {
+                let seconds = rng.draw_cost(*draws);
+                println!("{:<22} {:.2} ns per event", rng.name(), seconds * 1e9);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    let mut writer = args
+        .output
+        .map(|format| RecordWriter::new(format, std::io::stdout()));
+    if writer.is_none() {
+        println!("Build: {}", Manifest::current());
+    }
+
+    if let Some(path) = &args.resume {
+        let checkpoint = Checkpoint::load(path).unwrap_or_else(|err| exit_with_error(err));
+        let kind = checkpoint.model;
+        let mut model = kind
+            .restore(checkpoint.state)
+            .unwrap_or_else(|err| exit_with_error(err));
+        if writer.is_none() {
+            println!(
+                "Resuming model '{:?}' at time {:.2} with params {:?}",
+                kind,
+                model.current_time(),
+                model.get_params()
+            );
+        }
+        let label = kind.name();
+        set_checkpoints(&args, &label, kind, &mut model);
+        let stats = model.get_stats().clone();
+        add_progress(&args, &label, stats, &mut model);
+        let record = time_simulation(kind, &mut model, Instant::now(), 0.0, None);
+        write_transmission_tree(&args, &label, model.as_ref());
+        write_trace(&args, &label, model.as_ref());
+        report(&args, &mut writer, &record);
+        exit_on_violation(&label, model.invariant_violation());
+        return;
+    }
+
+    // Refused before any model runs, rather than once the baseline has run
+    if args.checkpoint.is_some()
+        && !params.common_random_numbers
+        && model_kinds.iter().any(|&k| k != ModelKind::Baseline)
+    {
+        exit_with_error(
+            "the ixa models can only save checkpoints with --common-random-numbers; add it or pick --model baseline",
+        );
+    }
 
-        if args.check_attack_rate {
-            assert!(model.get_stats().get_cum_incidence() > params.population / 2);
+    let jobs = replicate_jobs(&model_kinds, &params, args.replicates);
+    // The allocation counters are shared by the whole process, so concurrent
+    // runs would count each other's allocations
+    let threads = if cfg!(feature = "count-allocations") && args.threads != 1 {
+        eprintln!("warning: running one job at a time to count each run's allocations");
+        1
+    } else {
+        args.threads
+    };
+    let run = |job: &Job| {
+        let label = if args.replicates > 1 {
+            format!("{}-{}", job.kind.name(), job.replicate)
+        } else {
+            job.kind.name()
+        };
+        let mut model = job.kind.into_model(job.params.clone());
+        model.set_report_prefix(&format!("{label}-"));
+        set_checkpoints(&args, &label, job.kind, &mut model);
+        let stats = ModelStats::new(job.params.initial_infections);
+        add_progress(&args, &label, stats, &mut model);
+        let mut record =
+            time_model(job.kind, &mut model).unwrap_or_else(|err| exit_with_error(err));
+        record.replicate = job.replicate;
+        record.threads = if threads == 1 {
+            1
+        } else {
+            rayon::current_num_threads()
+        };
+        write_transmission_tree(&args, &label, model.as_ref());
+        write_trace(&args, &label, model.as_ref());
+        (record, label, model.invariant_violation().cloned())
+    };
+    if threads == 1 {
+        for job in &jobs {
+            if writer.is_none() {
+                println!(
+                    "Running model '{:?}' with params {:?}",
+                    job.kind, job.params
+                );
+            }
+            let (record, label, violation) = run(job);
+            report(&args, &mut writer, &record);
+            exit_on_violation(&label, violation.as_ref());
+        }
+        return;
+    }
+    // Each job runs its own model and saves its files; the records are
+    // reported in job order once they have all finished
+    let results = run_jobs(&jobs, threads, run);
+    for (record, _, _) in &results {
+        if writer.is_none() {
+            println!(
+                "Ran model '{:?}' with params {:?}",
+                record.model, record.params
+            );
         }
+        report(&args, &mut writer, record);
+    }
+    for (_, label, violation) in &results {
+        exit_on_violation(label, violation.as_ref());
+    }
+}
+
+// Exits with the first inconsistency `--check-invariants` found in a run,
+// once its record and files have been written
+fn exit_on_violation(label: &str, violation: Option<&InvariantViolation>) {
+    if let Some(violation) = violation {
+        exit_with_error(format!("{label}: {violation}"));
+    }
+}
+
+fn set_checkpoints(args: &Args, label: &str, kind: ModelKind, model: &mut Box<dyn SIRModel>) {
+    if let Some(prefix) = &args.checkpoint {
+        let checkpoints = checkpoint_files(
+            &format!("{prefix}-{label}"),
+            kind,
+            args.checkpoint_at.clone(),
+        );
+        model
+            .set_checkpoints(checkpoints)
+            .unwrap_or_else(|err| exit_with_error(err));
+    }
+}
+
+// `stats` are the model's stats at the point the reporter starts from
+fn add_progress(args: &Args, label: &str, stats: ModelStats, model: &mut Box<dyn SIRModel>) {
+    if let Some(seconds) = args.progress {
+        let interval = Duration::try_from_secs_f64(seconds)
+            .unwrap_or_else(|_| exit_with_error(format!("invalid --progress interval {seconds}")));
+        model.add_observer(Box::new(ProgressReporter::new(label, interval, stats)));
+    }
+}
+
+fn write_transmission_tree(args: &Args, label: &str, model: &dyn SIRModel) {
+    if let (Some(prefix), Some(tree)) = (&args.transmission_tree, model.get_transmission_tree()) {
+        let path = format!("{prefix}-{label}");
+        tree.write_files(&path).unwrap_or_else(|err| {
+            exit_with_error(format!("can't write {path}.csv and {path}.nwk: {err}"))
+        });
+    }
+}
+
+fn write_trace(args: &Args, label: &str, model: &dyn SIRModel) {
+    if let (Some(prefix), Some(trace)) = (&args.trace, model.get_trace()) {
+        let path = format!("{prefix}-{label}");
+        trace
+            .write_file(&path, label)
+            .unwrap_or_else(|err| exit_with_error(format!("can't write {path}.json: {err}")));
+    }
+}
+
+fn report(args: &Args, writer: &mut Option<RecordWriter<std::io::Stdout>>, record: &RunRecord) {
+    match writer {
+        Some(writer) => writer.write(record).unwrap(),
+        None => print_record(record),
+    }
+    if args.check_attack_rate {
+        assert!(record.cum_incidence > record.params.population / 2);
     }
 }
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::OnceLock};
+
+// The machine a result came from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Host {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    #[serde(default)]
+    pub cpu_model: String,
+    pub cpus: usize,
+}
+
+impl Host {
+    pub fn current() -> Self {
+        let hostname = std::fs::read_to_string("/etc/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .map(|name| name.trim().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        Self {
+            hostname,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpu_model: cpu_model().unwrap_or_else(|| "unknown".to_string()),
+            cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+fn cpu_model() -> Option<String> {
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        // "model name" on x86, "Model" or "Hardware" on some ARM boards
+        return ["model name", "Model", "Hardware"].iter().find_map(|key| {
+            cpuinfo.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                (name.trim() == *key).then(|| value.trim().to_string())
+            })
+        });
+    }
+    let output = std::process::Command::new("sysctl")
+        .args(["-n", "machdep.cpu.brand_string"])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// What produced a result: the build, captured by build.rs, and the machine.
+// Fields added after the benchmark history are empty in its older lines.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    pub crate_version: String,
+    // `git describe --dirty` of the build
+    pub commit: String,
+    #[serde(default)]
+    pub rustc: String,
+    #[serde(default)]
+    pub target: String,
+    // Cargo's profile ("debug" or "release") and optimization level
+    #[serde(default)]
+    pub profile: String,
+    #[serde(default)]
+    pub opt_level: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub ixa_version: String,
+    pub host: Host,
+}
+
+impl Manifest {
+    // Read once per process, since reading the CPU model can run a command
+    pub fn current() -> &'static Manifest {
+        static MANIFEST: OnceLock<Manifest> = OnceLock::new();
+        MANIFEST.get_or_init(|| Manifest {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: env!("SIR_BENCH_GIT_COMMIT").to_string(),
+            rustc: env!("SIR_BENCH_RUSTC_VERSION").to_string(),
+            target: env!("SIR_BENCH_TARGET").to_string(),
+            profile: env!("SIR_BENCH_PROFILE").to_string(),
+            opt_level: env!("SIR_BENCH_OPT_LEVEL").to_string(),
+            features: if cfg!(feature = "count-allocations") {
+                vec!["count-allocations".to_string()]
+            } else {
+                Vec::new()
+            },
+            ixa_version: env!("SIR_BENCH_IXA_VERSION").to_string(),
+            host: Host::current(),
+        })
+    }
+
+    // Describes the fields that differ from `other`, e.g. between the machine
+    // that saved a benchmark file and the one comparing against it
+    pub fn differences(&self, other: &Manifest) -> Vec<String> {
+        let fields = [
+            ("commit", &self.commit, &other.commit),
+            ("rustc", &self.rustc, &other.rustc),
+            ("target", &self.target, &other.target),
+            ("profile", &self.profile, &other.profile),
+            ("opt level", &self.opt_level, &other.opt_level),
+            ("ixa", &self.ixa_version, &other.ixa_version),
+            ("host", &self.host.hostname, &other.host.hostname),
+            ("CPU", &self.host.cpu_model, &other.host.cpu_model),
+        ];
+        let mut differences: Vec<String> = fields
+            .into_iter()
+            .filter(|(_, a, b)| a != b)
+            .map(|(name, a, b)| format!("{name} {a} -> {b}"))
+            .collect();
+        if self.features != other.features {
+            differences.push(format!(
+                "features {:?} -> {:?}",
+                self.features, other.features
+            ));
+        }
+        differences
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sir-bench {} ({}), {}, {} profile (opt-level {}), ixa {}, {}",
+            self.crate_version,
+            self.commit,
+            self.rustc,
+            self.profile,
+            self.opt_level,
+            self.ixa_version,
+            self.target
+        )?;
+        for feature in &self.features {
+            write!(f, " +{feature}")?;
+        }
+        write!(
+            f,
+            " on {} ({} core{}, {})",
+            self.host.cpu_model,
+            self.host.cpus,
+            if self.host.cpus == 1 { "" } else { "s" },
+            self.host.hostname
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_the_build() {
+        let manifest = Manifest::current();
+        assert_eq!(manifest.crate_version, env!("CARGO_PKG_VERSION"));
+        assert!(manifest.rustc.starts_with("rustc "));
+        assert_eq!(
+            manifest.profile,
+            if cfg!(debug_assertions) {
+                "debug"
+            } else {
+                "release"
+            }
+        );
+        assert!(manifest.host.cpus >= 1);
+        assert!(manifest.to_string().contains(&manifest.ixa_version));
+
+        let other = Manifest {
+            rustc: "rustc 1.0.0".to_string(),
+            ..manifest.clone()
+        };
+        assert!(manifest.differences(manifest).is_empty());
+        assert_eq!(
+            other.differences(manifest),
+            vec![format!("rustc rustc 1.0.0 -> {}", manifest.rustc)]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::stats::ModelStats;
+use std::{cell::RefCell, rc::Rc};
+
+// Receives the events of a run from any `SIRModel`, for metrics, logging or
+// validation. Seeded infections aren't reported, like in `ModelStats`, and
+// rejected contacts aren't events. Every method does nothing by default.
+pub trait SimulationObserver {
+    // `infector` is only known when tracking transmission
+    fn on_infection(&mut self, _t: f64, _person: usize, _infector: Option<usize>) {}
+    fn on_recovery(&mut self, _t: f64, _person: usize) {}
+    // Called before the events at `t` when the time moves forward
+    fn on_time_advance(&mut self, _t: f64) {}
+    fn on_finish(&mut self, _t: f64, _stats: &ModelStats) {}
+}
+
+// Lets the caller keep a handle to an observer it has registered
+impl<T: SimulationObserver> SimulationObserver for Rc<RefCell<T>> {
+    fn on_infection(&mut self, t: f64, person: usize, infector: Option<usize>) {
+        self.borrow_mut().on_infection(t, person, infector);
+    }
+    fn on_recovery(&mut self, t: f64, person: usize) {
+        self.borrow_mut().on_recovery(t, person);
+    }
+    fn on_time_advance(&mut self, t: f64) {
+        self.borrow_mut().on_time_advance(t);
+    }
+    fn on_finish(&mut self, t: f64, stats: &ModelStats) {
+        self.borrow_mut().on_finish(t, stats);
+    }
+}
+
+// Does nothing, to measure the cost of notifying observers
+pub struct NoopObserver;
+
+impl SimulationObserver for NoopObserver {}
+
+// The observers registered with a model
+#[derive(Default)]
+pub struct Observers {
+    observers: Vec<Box<dyn SimulationObserver>>,
+    // Time of the last event reported
+    time: f64,
+}
+
+impl Observers {
+    pub fn add(&mut self, observer: Box<dyn SimulationObserver>) {
+        self.observers.push(observer);
+    }
+    // Lets models skip working out what to report
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+    // For a model that starts again from time 0
+    pub fn reset(&mut self) {
+        self.time = 0.0;
+    }
+    fn advance(&mut self, t: f64) {
+        if t > self.time {
+            self.time = t;
+            for observer in &mut self.observers {
+                observer.on_time_advance(t);
+            }
+        }
+    }
+    pub fn infection(&mut self, t: f64, person: usize, infector: Option<usize>) {
+        self.advance(t);
+        for observer in &mut self.observers {
+            observer.on_infection(t, person, infector);
+        }
+    }
+    pub fn recovery(&mut self, t: f64, person: usize) {
+        self.advance(t);
+        for observer in &mut self.observers {
+            observer.on_recovery(t, person);
+        }
+    }
+    pub fn finish(&mut self, t: f64, stats: &ModelStats) {
+        for observer in &mut self.observers {
+            observer.on_finish(t, stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters};
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Infection(f64, usize, Option<usize>),
+        Recovery(f64, usize),
+        TimeAdvance(f64),
+        Finish(f64, usize),
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<Event>);
+
+    impl SimulationObserver for Recorder {
+        fn on_infection(&mut self, t: f64, person: usize, infector: Option<usize>) {
+            self.0.push(Event::Infection(t, person, infector));
+        }
+        fn on_recovery(&mut self, t: f64, person: usize) {
+            self.0.push(Event::Recovery(t, person));
+        }
+        fn on_time_advance(&mut self, t: f64) {
+            self.0.push(Event::TimeAdvance(t));
+        }
+        fn on_finish(&mut self, t: f64, stats: &ModelStats) {
+            self.0.push(Event::Finish(t, stats.get_cum_incidence()));
+        }
+    }
+
+    #[test]
+    fn models_report_the_same_events() {
+        let params = Parameters {
+            population: 2000,
+            max_time: 1000.0,
+            track_transmission: true,
+            common_random_numbers: true,
+            ..Parameters::default()
+        };
+        let mut runs = Vec::new();
+        for kind in ModelKind::all() {
+            let recorder = Rc::new(RefCell::new(Recorder::default()));
+            let mut model = kind.into_model(params.clone());
+            model.add_observer(Box::new(recorder.clone()));
+            model.run().unwrap();
+
+            let events = recorder.take().0;
+            let infections = events
+                .iter()
+                .filter(|e| matches!(e, Event::Infection(..)))
+                .count();
+            assert_eq!(infections, model.get_stats().get_cum_incidence());
+            assert_eq!(
+                events.last(),
+                Some(&Event::Finish(model.current_time(), infections))
+            );
+            runs.push(events);
+        }
+        assert!(runs[0].len() > 100);
+        for run in &runs[1..] {
+            assert_eq!(&runs[0], run);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    ModelKind,
+    alloc::AllocStats,
+    base::Parameters,
+    manifest::Manifest,
+    stats::{EventCounts, EventTimes},
+    stop::StopReason,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RunRecord {
+    pub model: ModelKind,
+    pub replicate: usize,
+    // Size of the thread pool the run was part of; timings from runs with
+    // more than one thread may include contention
+    pub threads: usize,
+    #[serde(flatten)]
+    pub params: Parameters,
+    pub setup_seconds: f64,
+    pub simulate_seconds: f64,
+    pub total_seconds: f64,
+    pub final_time: f64,
+    pub stop_reason: StopReason,
+    pub cum_incidence: usize,
+    pub peak_prevalence: usize,
+    // Process-wide high water mark, so it includes any earlier runs
+    pub peak_rss_bytes: Option<u64>,
+    // Only measured with the `count-allocations` feature
+    pub setup_alloc: Option<AllocStats>,
+    pub simulate_alloc: Option<AllocStats>,
+    pub events: EventCounts,
+    // Only measured with `profile_events`
+    pub event_times: Option<EventTimes>,
+    // The build and machine that produced the record
+    pub manifest: Manifest,
+}
+
+// Writes one record per line (JSON lines) or a CSV table with a header row
+// taken from the field names of the first record. Nested structs become
+// dotted columns in CSV, e.g. `setup_alloc.peak_bytes`.
+pub struct RecordWriter<W: Write> {
+    format: OutputFormat,
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(format: OutputFormat, writer: W) -> Self {
+        Self {
+            format,
+            writer,
+            header_written: false,
+        }
+    }
+
+    pub fn write<T: Serialize>(&mut self, record: &T) -> io::Result<()> {
+        let value = serde_json::to_value(record)?;
+        match self.format {
+            OutputFormat::Json => writeln!(self.writer, "{value}"),
+            OutputFormat::Csv => {
+                if !value.is_object() {
+                    return Err(io::Error::other("CSV records must be structs"));
+                }
+                let mut fields = Vec::new();
+                flatten("", &value, &mut fields);
+                if !self.header_written {
+                    let header: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+                    writeln!(self.writer, "{}", header.join(","))?;
+                    self.header_written = true;
+                }
+                let row: Vec<String> = fields.iter().map(|(_, v)| csv_field(v)).collect();
+                writeln!(self.writer, "{}", row.join(","))
+            }
+        }
+    }
+}
+
+fn flatten<'a>(prefix: &str, value: &'a Value, out: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&key, value, out);
+            }
+        }
+        _ => out.push((prefix.to_string(), value)),
+    }
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) if s.contains([',', '"', '\n']) => {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        }
+        Value::String(s) => s.clone(),
+        other => csv_field(&Value::String(other.to_string())),
+    }
+}
+
+// Peak resident set size of this process, where the platform reports it
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> RunRecord {
+        RunRecord {
+            model: ModelKind::Baseline,
+            replicate: 0,
+            threads: 1,
+            params: Parameters::default(),
+            setup_seconds: 0.5,
+            simulate_seconds: 1.5,
+            total_seconds: 2.0,
+            final_time: 42.0,
+            stop_reason: StopReason::Extinction,
+            cum_incidence: 580,
+            peak_prevalence: 100,
+            peak_rss_bytes: None,
+            setup_alloc: None,
+            simulate_alloc: Some(AllocStats {
+                peak_bytes: 2048,
+                total_bytes: 4096,
+                allocations: 3,
+            }),
+            events: EventCounts::default(),
+            event_times: None,
+            manifest: Manifest::current().clone(),
+        }
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut writer = RecordWriter::new(OutputFormat::Json, Vec::new());
+        writer.write(&record()).unwrap();
+        writer.write(&record()).unwrap();
+        let out = String::from_utf8(writer.writer).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let value: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["model"], "baseline");
+        assert_eq!(value["r0"], 1.5);
+        assert_eq!(value["cum_incidence"], 580);
+        assert_eq!(value["stop_reason"], "extinction");
+        assert_eq!(value["peak_rss_bytes"], Value::Null);
+    }
+
+    #[test]
+    fn csv() {
+        let mut writer = RecordWriter::new(OutputFormat::Csv, Vec::new());
+        writer.write(&record()).unwrap();
+        writer.write(&record()).unwrap();
+        let out = String::from_utf8(writer.writer).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("model,replicate,threads,r0,infectious_period,population,"));
+        assert!(lines[0].contains(
+            ",simulate_alloc.allocations,events.infection_attempts,events.infections,\
+             events.rejected_contacts,events.recoveries,events.plans_scheduled,\
+             events.queries,event_times,manifest.crate_version,manifest.commit,"
+        ));
+        assert!(lines[0].ends_with(",manifest.host.cpu_model,manifest.host.cpus"));
+        assert!(lines[1].starts_with("baseline,0,1,1.5,3.0,1000,"));
+        assert!(lines[1].contains(&format!(
+            ",580,100,,,2048,4096,3,0,0,0,0,0,0,,{},",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert_eq!(lines[1], lines[2]);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field(&Value::String("a,b".into())), "\"a,b\"");
+        assert_eq!(csv_field(&Value::Bool(true)), "true");
+    }
+}
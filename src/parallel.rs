@@ -0,0 +1,75 @@
+use crate::{ModelKind, base::Parameters};
+use rayon::prelude::*;
+
+// One independent model run
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub kind: ModelKind,
+    pub replicate: usize,
+    pub params: Parameters,
+}
+
+// `replicates` runs of each model, where replicate r uses seed `params.seed + r`
+pub fn replicate_jobs(kinds: &[ModelKind], params: &Parameters, replicates: usize) -> Vec<Job> {
+    kinds
+        .iter()
+        .flat_map(|&kind| {
+            (0..replicates).map(move |replicate| Job {
+                kind,
+                replicate,
+                params: Parameters {
+                    seed: params.seed.wrapping_add(replicate as u64),
+                    ..params.clone()
+                },
+            })
+        })
+        .collect()
+}
+
+// Runs the jobs on a pool of `threads` threads (0 for one per core) and
+// returns the results in the same order as the jobs. Each job builds its own
+// model, since ixa's Context can't be shared between threads.
+pub fn run_jobs<R, F>(jobs: &[Job], threads: usize, run: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(&Job) -> R + Sync,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    pool.install(|| jobs.par_iter().map(&run).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_matches_serial() {
+        let params = Parameters {
+            population: 500,
+            ..Parameters::default()
+        };
+        let jobs = replicate_jobs(&ModelKind::all(), &params, 4);
+        assert_eq!(jobs.len(), 4 * ModelKind::all().len());
+        assert_eq!(jobs[5].kind, ModelKind::Ixa);
+        assert_eq!(jobs[5].params.seed, params.seed + 1);
+
+        let run = |job: &Job| {
+            let mut model = job.kind.into_model(job.params.clone());
+            model.run().unwrap();
+            (
+                job.kind,
+                job.replicate,
+                model.current_time(),
+                model.get_stats().get_cum_incidence(),
+            )
+        };
+        let serial = run_jobs(&jobs, 1, run);
+        let parallel = run_jobs(&jobs, 4, run);
+        assert_eq!(serial, parallel);
+        // Replicates differ
+        assert_ne!(serial[0].3, serial[1].3);
+    }
+}
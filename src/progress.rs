@@ -0,0 +1,146 @@
+use crate::{observer::SimulationObserver, stats::ModelStats};
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+// Reading the clock is slow next to a baseline event, so only check it this often
+const EVENTS_PER_CHECK: usize = 1024;
+
+// Prints the state of a run to stderr every `interval` of wall time, driven
+// by the model's observer hooks. Models without one don't pay for it.
+pub struct ProgressReporter<W = io::Stderr> {
+    label: String,
+    interval: Duration,
+    writer: W,
+    // Kept up to date from the events, starting from the model's stats
+    stats: ModelStats,
+    time: f64,
+    events: usize,
+    start: Instant,
+    last_report: Instant,
+    events_at_last_report: usize,
+}
+
+impl ProgressReporter {
+    pub fn new(label: &str, interval: Duration, stats: ModelStats) -> Self {
+        Self::with_writer(label, interval, stats, io::stderr())
+    }
+}
+
+impl<W: Write> ProgressReporter<W> {
+    pub fn with_writer(label: &str, interval: Duration, stats: ModelStats, writer: W) -> Self {
+        let now = Instant::now();
+        Self {
+            label: label.to_string(),
+            interval,
+            writer,
+            stats,
+            time: 0.0,
+            events: 0,
+            start: now,
+            last_report: now,
+            events_at_last_report: 0,
+        }
+    }
+
+    fn event(&mut self, t: f64) {
+        self.time = t;
+        self.events += 1;
+        if self.events.is_multiple_of(EVENTS_PER_CHECK)
+            && self.last_report.elapsed() >= self.interval
+        {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let now = Instant::now();
+        let seconds = now.duration_since(self.last_report).as_secs_f64();
+        let rate = (self.events - self.events_at_last_report) as f64 / seconds.max(1e-9);
+        // Progress is best effort; a closed stderr shouldn't stop the run
+        let _ = writeln!(
+            self.writer,
+            "[{}] t={:.2} prevalence={} incidence={} events={} ({:.0}/s)",
+            self.label,
+            self.time,
+            self.stats.get_prevalence(),
+            self.stats.get_cum_incidence(),
+            self.events,
+            rate
+        );
+        self.last_report = now;
+        self.events_at_last_report = self.events;
+    }
+}
+
+impl<W: Write> SimulationObserver for ProgressReporter<W> {
+    fn on_infection(&mut self, t: f64, _person: usize, _infector: Option<usize>) {
+        self.stats.record_infection();
+        self.event(t);
+    }
+    fn on_recovery(&mut self, t: f64, _person: usize) {
+        self.stats.record_recovery();
+        self.event(t);
+    }
+    fn on_finish(&mut self, t: f64, _stats: &ModelStats) {
+        self.time = t;
+        let _ = writeln!(
+            self.writer,
+            "[{}] finished at t={:.2} after {} events in {:.2}s",
+            self.label,
+            t,
+            self.events,
+            self.start.elapsed().as_secs_f64()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters};
+    use std::{cell::RefCell, rc::Rc};
+
+    // Lets the test read what a reporter owned by the model wrote
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reports_the_model_state() {
+        let params = Parameters {
+            population: 5000,
+            ..Parameters::default()
+        };
+        for kind in ModelKind::all() {
+            let out = Shared::default();
+            let mut model = kind.into_model(params.clone());
+            model.add_observer(Box::new(ProgressReporter::with_writer(
+                &kind.name(),
+                Duration::ZERO,
+                ModelStats::new(params.initial_infections),
+                out.clone(),
+            )));
+            model.run().unwrap();
+
+            let text = String::from_utf8(out.0.take()).unwrap();
+            let lines: Vec<_> = text.lines().collect();
+            // One report per check, then the summary
+            let events = model.get_stats().get_cum_incidence() * 2 + params.initial_infections;
+            assert!(lines.len() > 1);
+            assert!(lines.len() <= events / EVENTS_PER_CHECK + 1);
+            let last = lines[lines.len() - 2];
+            assert!(last.starts_with(&format!("[{}] t=", kind.name())));
+            assert!(lines[lines.len() - 1].contains("finished"));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// Exact results for the stochastic SIR model, computed from its embedded jump
+// chain. Both implementations make infection attempts at rate r0 / D per
+// infectious person against a uniformly random member of the population and
+// recover at rate 1 / D, so from the state (s, i) the next change is an
+// infection with probability r0 * s / N / (r0 * s / N + 1). With
+// `direct_sampling` they make attempts at rate r0 / D * s / N per infectious
+// person against a susceptible person instead, which is the same chain.
+//
+// This is O(N^2) in time and memory, so it is meant for populations of up to
+// a few hundred people.
+
+pub struct Reference {
+    // Probability that exactly k people are infected after seeding
+    pub final_size: Vec<f64>,
+    // Expected time until nobody is infectious
+    pub expected_duration: f64,
+}
+
+pub fn solve(
+    population: usize,
+    initial_infections: usize,
+    r0: f64,
+    infectious_period: f64,
+) -> Reference {
+    let n = population as f64;
+    let s0 = population - initial_infections;
+    let gamma = 1.0 / infectious_period;
+    let beta = r0 * gamma;
+
+    // visits[s][i] is the probability that the chain passes through (s, i).
+    // Each step lowers 2s + i by one, so sweeping that downwards visits every
+    // state after all of its predecessors.
+    let mut visits = vec![vec![0.0; population + 1]; s0 + 1];
+    visits[s0][initial_infections] = 1.0;
+
+    let mut final_size = vec![0.0; s0 + 1];
+    let mut expected_duration = 0.0;
+    for level in (0..=2 * s0 + initial_infections).rev() {
+        for s in 0..=s0.min(level / 2) {
+            let i = level - 2 * s;
+            if i > population - s {
+                continue;
+            }
+            let p = visits[s][i];
+            if p == 0.0 {
+                continue;
+            }
+            if i == 0 {
+                final_size[s0 - s] += p;
+                continue;
+            }
+
+            let infection_rate = beta * (s as f64) / n * (i as f64);
+            let recovery_rate = gamma * (i as f64);
+            let total = infection_rate + recovery_rate;
+            expected_duration += p / total;
+            if s > 0 {
+                visits[s - 1][i + 1] += p * infection_rate / total;
+            }
+            visits[s][i - 1] += p * recovery_rate / total;
+        }
+    }
+
+    Reference {
+        final_size,
+        expected_duration,
+    }
+}
+
+// The large population limit of the same model, for populations too big to
+// solve exactly: the fraction of the population ever infected in a major
+// outbreak, and its standard deviation in a population of the given size.
+pub struct FinalSize {
+    pub fraction: f64,
+    pub std_dev: f64,
+}
+
+pub fn final_size(population: usize, initial_infections: usize, r0: f64) -> FinalSize {
+    let s0 = 1.0 - initial_infections as f64 / population as f64;
+    // 1 - z = s0 * exp(-r0 * z), iterated down from z = 1 to the largest root
+    let mut z: f64 = 1.0;
+    for _ in 0..10_000 {
+        let next = 1.0 - s0 * (-r0 * z).exp();
+        if (next - z).abs() < 1e-15 {
+            break;
+        }
+        z = next;
+    }
+    // Central limit theorem for the final size with exponential infectious
+    // periods (Andersson & Britton 2000, theorem 4.2); infinite near r0 = 1
+    let variance = z * (1.0 - z) * (1.0 + r0 * r0 * (1.0 - z)) / (1.0 - r0 * (1.0 - z)).powi(2);
+    FinalSize {
+        fraction: z,
+        std_dev: (variance / population as f64).sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use statrs::distribution::{ChiSquared, ContinuousCDF};
+    use statrs::statistics::Statistics;
+
+    use super::*;
+    use crate::{ModelKind, base::Parameters};
+
+    #[test]
+    fn single_infection() {
+        // One infectious person among two: infects the other with probability
+        // (r0 / 2) / (r0 / 2 + 1)
+        let reference = solve(2, 1, 2.0, 1.0);
+        assert_relative_eq!(reference.final_size[0], 0.5);
+        assert_relative_eq!(reference.final_size[1], 0.5);
+        // 1 / (rate out of (1, 1)) + P(infect) / (rate out of (0, 2)) + 1 / (rate out of (0, 1))
+        assert_relative_eq!(reference.expected_duration, 0.5 + 0.5 * 0.5 + 0.5 * 1.0);
+    }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        let reference = solve(200, 1, 1.5, 3.0);
+        assert_relative_eq!(
+            reference.final_size.iter().sum::<f64>(),
+            1.0,
+            epsilon = 1e-9
+        );
+        // Minor outbreaks are common, and so are major ones
+        assert!(reference.final_size[0] > 0.1);
+        assert!(reference.final_size[50..].iter().sum::<f64>() > 0.25);
+    }
+
+    #[test]
+    fn final_size_limit() {
+        let limit = final_size(100_000, 5, 1.5);
+        assert_relative_eq!(limit.fraction, 0.5829, epsilon = 1e-3);
+        assert_relative_eq!(limit.std_dev, 1.83 / 100_000f64.sqrt(), epsilon = 1e-4);
+
+        // Close to the mean of the major outbreaks in the exact solution
+        let reference = solve(400, 1, 1.5, 3.0);
+        let major = &reference.final_size[80..];
+        let mean = major
+            .iter()
+            .enumerate()
+            .map(|(k, p)| (k + 81) as f64 / 400.0 * p)
+            .sum::<f64>()
+            / major.iter().sum::<f64>();
+        let limit = final_size(400, 1, 1.5);
+        assert!((mean - limit.fraction).abs() < limit.std_dev);
+    }
+
+    // Chi-square statistic p-value, pooling sizes with few expected runs
+    fn chi_square_p_value(observed: &[usize], expected: &[f64]) -> f64 {
+        let mut bins: Vec<(f64, f64)> = Vec::new();
+        let (mut o, mut e) = (0.0, 0.0);
+        for (&observed, &expected) in observed.iter().zip(expected) {
+            o += observed as f64;
+            e += expected;
+            if e >= 5.0 {
+                bins.push((o, e));
+                (o, e) = (0.0, 0.0);
+            }
+        }
+        if let Some(last) = bins.last_mut() {
+            last.0 += o;
+            last.1 += e;
+        }
+
+        let statistic: f64 = bins.iter().map(|(o, e)| (o - e).powi(2) / e).sum();
+        let df = (bins.len() - 1) as f64;
+        1.0 - ChiSquared::new(df).unwrap().cdf(statistic)
+    }
+
+    fn check_conformance(kind: ModelKind, direct_sampling: bool) {
+        let replicates = 2000;
+        let params = Parameters {
+            r0: 2.0,
+            infectious_period: 3.0,
+            population: 60,
+            initial_infections: 2,
+            max_time: 1e9,
+            direct_sampling,
+            ..Parameters::default()
+        };
+        let reference = solve(
+            params.population,
+            params.initial_infections,
+            params.r0,
+            params.infectious_period,
+        );
+
+        let mut sizes = vec![0; reference.final_size.len()];
+        let mut durations = Vec::with_capacity(replicates);
+        for seed in 0..replicates as u64 {
+            let mut model = kind.into_model(Parameters {
+                seed,
+                ..params.clone()
+            });
+            model.run().unwrap();
+            sizes[model.get_stats().get_cum_incidence()] += 1;
+            if direct_sampling {
+                assert_eq!(model.get_stats().get_events().rejected_contacts, 0);
+            }
+            durations.push(model.current_time());
+        }
+
+        let expected: Vec<f64> = reference
+            .final_size
+            .iter()
+            .map(|p| p * replicates as f64)
+            .collect();
+        let p_value = chi_square_p_value(&sizes, &expected);
+        assert!(p_value > 0.001, "{kind:?} final size p-value {p_value}");
+
+        let mean = durations.iter().mean();
+        let std_error = durations.iter().std_dev() / (replicates as f64).sqrt();
+        assert!(
+            (mean - reference.expected_duration).abs() < 4.0 * std_error,
+            "{kind:?} mean duration {mean}, expected {}",
+            reference.expected_duration
+        );
+    }
+
+    #[test]
+    fn baseline_conformance() {
+        check_conformance(ModelKind::Baseline, false);
+    }
+
+    #[test]
+    fn ixa_conformance() {
+        check_conformance(ModelKind::Ixa, false);
+    }
+
+    #[test]
+    fn ixa_no_queries_conformance() {
+        check_conformance(ModelKind::IxaNoQueries, false);
+    }
+
+    #[test]
+    fn ixa_infected_set_conformance() {
+        check_conformance(ModelKind::IxaInfectedSet, false);
+    }
+
+    #[test]
+    fn direct_sampling_conformance() {
+        for kind in ModelKind::all() {
+            check_conformance(kind, true);
+        }
+    }
+}
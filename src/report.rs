@@ -0,0 +1,251 @@
+use crate::{
+    ModelKind,
+    history::{HistoryEntry, format_timestamp},
+    manifest::Manifest,
+    reference::final_size,
+};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Standard deviations from the large population final size that the outcome
+// of a run can be before the report flags it
+const FINAL_SIZE_TOLERANCE: f64 = 3.0;
+
+// Where the timings in a report came from
+pub enum Source {
+    Ran { warmup: usize, runs: usize },
+    History(PathBuf),
+}
+
+// The latest timing of each scenario and model, leaving out `reuse` runs
+// since they only time the epidemic loop. Ordered by scenario as they first
+// appear, then by model.
+pub fn latest(entries: &[HistoryEntry]) -> Vec<HistoryEntry> {
+    let mut latest: Vec<HistoryEntry> = Vec::new();
+    for entry in entries.iter().filter(|e| !e.result.reuse) {
+        match latest.iter_mut().find(|l| {
+            l.result.scenario == entry.result.scenario && l.result.model == entry.result.model
+        }) {
+            Some(l) if l.timestamp <= entry.timestamp => *l = entry.clone(),
+            Some(_) => {}
+            None => latest.push(entry.clone()),
+        }
+    }
+    let scenarios: Vec<String> = latest.iter().fold(Vec::new(), |mut scenarios, e| {
+        if !scenarios.contains(&e.result.scenario) {
+            scenarios.push(e.result.scenario.clone());
+        }
+        scenarios
+    });
+    let models = ModelKind::all();
+    latest.sort_by_key(|e| {
+        (
+            scenarios.iter().position(|s| *s == e.result.scenario),
+            models.iter().position(|m| *m == e.result.model),
+        )
+    });
+    latest
+}
+
+// Writes a Markdown report of `entries`, one table per scenario
+pub fn write_markdown<W: Write>(
+    mut w: W,
+    entries: &[HistoryEntry],
+    source: &Source,
+) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    writeln!(w, "# Results")?;
+    writeln!(w)?;
+    match source {
+        Source::Ran { warmup, runs } => writeln!(
+            w,
+            "Generated on {} UTC from {runs} timed runs of each model ({warmup} warmup), \
+             each in a new model and including setup.",
+            format_timestamp(now)
+        )?,
+        Source::History(path) => writeln!(
+            w,
+            "Generated on {} UTC from the latest results in `{}`.",
+            format_timestamp(now),
+            path.display()
+        )?,
+    }
+
+    let mut scenarios: Vec<&str> = Vec::new();
+    for entry in entries {
+        if !scenarios.contains(&entry.result.scenario.as_str()) {
+            scenarios.push(&entry.result.scenario);
+        }
+    }
+    for scenario in scenarios {
+        let results: Vec<_> = entries
+            .iter()
+            .filter(|e| e.result.scenario == scenario)
+            .collect();
+        write_scenario(&mut w, scenario, &results)?;
+    }
+
+    write_environment(&mut w, entries)?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "Regenerate with `just report` or `cargo run --release --bin sir -- report`."
+    )
+}
+
+fn write_scenario<W: Write>(
+    w: &mut W,
+    scenario: &str,
+    results: &[&HistoryEntry],
+) -> io::Result<()> {
+    let params = &results[0].result.params;
+    writeln!(w)?;
+    writeln!(w, "## {scenario}")?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "Population {}, r0 {}, infectious period {}, {} initial infections, seed {}, \
+         max time {}.",
+        params.population,
+        params.r0,
+        params.infectious_period,
+        params.initial_infections,
+        params.seed,
+        params.max_time
+    )?;
+    writeln!(w)?;
+
+    let baseline = results
+        .iter()
+        .find(|e| e.result.model == ModelKind::Baseline)
+        .map(|e| e.result.mean());
+    let expected = final_size(params.population, params.initial_infections, params.r0);
+    writeln!(
+        w,
+        "| model | mean (ms) | std dev (ms) | runs | time vs baseline | infected | final size check |"
+    )?;
+    writeln!(w, "|---|---:|---:|---:|---:|---:|---|")?;
+    for entry in results {
+        let result = &entry.result;
+        let relative = baseline
+            .map(|b| format!("{:.2}x", result.mean() / b))
+            .unwrap_or_else(|| "-".to_string());
+        let (infected, check) = match result.cum_incidence {
+            Some(incidence) => {
+                let fraction = (incidence + result.params.initial_infections) as f64
+                    / params.population as f64;
+                let deviations = (fraction - expected.fraction) / expected.std_dev;
+                let check = if deviations.abs() <= FINAL_SIZE_TOLERANCE {
+                    format!("ok ({deviations:+.1} sd)")
+                } else {
+                    format!("**off by {deviations:+.1} sd**")
+                };
+                (format!("{:.1}%", fraction * 100.0), check)
+            }
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let std_dev = if result.samples.len() > 1 {
+            format!("{:.3}", result.std_dev() * 1000.0)
+        } else {
+            "-".to_string()
+        };
+        writeln!(
+            w,
+            "| {} | {:.3} | {} | {} | {} | {} | {} |",
+            result.model.name(),
+            result.mean() * 1000.0,
+            std_dev,
+            result.samples.len(),
+            relative,
+            infected,
+            check
+        )?;
+    }
+    writeln!(w)?;
+    writeln!(
+        w,
+        "The final size equation gives {:.1}% ± {:.1}% of the population infected in a \
+         major outbreak; runs more than {FINAL_SIZE_TOLERANCE} standard deviations away \
+         are flagged.",
+        expected.fraction * 100.0,
+        expected.std_dev * 100.0
+    )
+}
+
+fn write_environment<W: Write>(w: &mut W, entries: &[HistoryEntry]) -> io::Result<()> {
+    writeln!(w)?;
+    writeln!(w, "## Environment")?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "| commit | rustc | profile | target | ixa | CPU | cores | host |"
+    )?;
+    writeln!(w, "|---|---|---|---|---|---|---:|---|")?;
+    let mut seen: Vec<&Manifest> = Vec::new();
+    for manifest in entries.iter().map(|e| &e.manifest) {
+        if seen.contains(&manifest) {
+            continue;
+        }
+        seen.push(manifest);
+        let mut profile = format!("{} (opt-level {})", manifest.profile, manifest.opt_level);
+        for feature in &manifest.features {
+            profile.push_str(&format!(", {feature}"));
+        }
+        writeln!(
+            w,
+            "| `{}` | {} | {} | {} | {} | {} | {} | {} |",
+            manifest.commit,
+            manifest.rustc,
+            profile,
+            manifest.target,
+            manifest.ixa_version,
+            manifest.host.cpu_model,
+            manifest.host.cpus,
+            manifest.host.hostname
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bench::run_benchmark, scenario::Scenario};
+
+    #[test]
+    fn reports_each_scenario() {
+        let params = Scenario::Sir.params();
+        let mut entries: Vec<_> = ModelKind::all()
+            .into_iter()
+            .map(|kind| {
+                HistoryEntry::new(run_benchmark("sir", kind, &params, 0, 1, false).unwrap())
+            })
+            .collect();
+        // An older result of the same model is left out
+        let mut older = entries[1].clone();
+        older.timestamp -= 1;
+        older.result.samples = vec![100.0];
+        entries.insert(0, older);
+
+        let latest = latest(&entries);
+        assert_eq!(latest.len(), ModelKind::all().len());
+        assert_eq!(latest[0].result.model, ModelKind::Baseline);
+        assert!(latest[1].result.mean() < 100.0);
+
+        let mut out = Vec::new();
+        write_markdown(&mut out, &latest, &Source::Ran { warmup: 0, runs: 1 }).unwrap();
+        let markdown = String::from_utf8(out).unwrap();
+        assert!(markdown.contains("## sir"));
+        assert!(markdown.contains("| baseline |"));
+        assert!(markdown.contains("| 1.00x |"));
+        assert!(markdown.contains("## Environment"));
+        // The scenario's runs all end in major outbreaks close to the limit
+        assert_eq!(markdown.matches("| ok (").count(), latest.len());
+        assert!(!markdown.contains("**off"));
+    }
+}
@@ -0,0 +1,277 @@
+use clap::ValueEnum;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::{ChaCha8Rng, ChaCha12Rng};
+use rand_distr::Exp;
+use rand_pcg::Pcg64;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use serde::{Deserialize, Serialize};
+use std::hint::black_box;
+use std::time::Instant;
+
+// rand's SmallRng and StdRng don't expose their state, so checkpoints can't
+// save them. Xoshiro256PlusPlus and ChaCha12Rng are the same algorithms,
+// seeded the same way, from crates that can (the tests check that they give
+// the same numbers). The default is rand's SmallRng on 64-bit platforms.
+pub type DefaultRng = Xoshiro256PlusPlus;
+
+// The generator behind the baseline's draws, and ixa's with
+// `common_random_numbers`. ixa otherwise always uses its own generator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RngKind {
+    // Xoshiro256++, which files saved before it was merged with `small` call
+    // `xoshiro256-plus-plus`
+    #[default]
+    #[value(alias = "xoshiro256-plus-plus")]
+    #[serde(alias = "xoshiro256-plus-plus")]
+    Small,
+    // ChaCha12
+    Std,
+    Chacha8,
+    Pcg64,
+}
+
+impl RngKind {
+    pub fn all() -> Vec<Self> {
+        vec![Self::Small, Self::Std, Self::Chacha8, Self::Pcg64]
+    }
+    // The name used on the command line, e.g. "chacha8"
+    pub fn name(self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+    // For callers that can't be generic over the generator
+    pub fn seeded(self, seed: u64) -> AnyRng {
+        match self {
+            RngKind::Small => AnyRng::Small(DefaultRng::seed_from_u64(seed)),
+            RngKind::Std => AnyRng::Std(ChaCha12Rng::seed_from_u64(seed)),
+            RngKind::Chacha8 => AnyRng::Chacha8(ChaCha8Rng::seed_from_u64(seed)),
+            RngKind::Pcg64 => AnyRng::Pcg64(Pcg64::seed_from_u64(seed)),
+        }
+    }
+    // Average seconds per SIR event's worth of draws: a pair of event times
+    // and a contact index
+    pub fn draw_cost(self, draws: usize) -> f64 {
+        match self {
+            RngKind::Small => time_draws::<DefaultRng>(draws),
+            RngKind::Std => time_draws::<ChaCha12Rng>(draws),
+            RngKind::Chacha8 => time_draws::<ChaCha8Rng>(draws),
+            RngKind::Pcg64 => time_draws::<Pcg64>(draws),
+        }
+    }
+}
+
+// One of the generators, chosen at runtime
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AnyRng {
+    #[serde(alias = "Xoshiro256PlusPlus")]
+    Small(DefaultRng),
+    Std(ChaCha12Rng),
+    Chacha8(ChaCha8Rng),
+    Pcg64(Pcg64),
+}
+
+macro_rules! dispatch {
+    ($rng:expr, $inner:ident => $call:expr) => {
+        match $rng {
+            AnyRng::Small($inner) => $call,
+            AnyRng::Std($inner) => $call,
+            AnyRng::Chacha8($inner) => $call,
+            AnyRng::Pcg64($inner) => $call,
+        }
+    };
+}
+
+impl RngCore for AnyRng {
+    fn next_u32(&mut self) -> u32 {
+        dispatch!(self, rng => rng.next_u32())
+    }
+    fn next_u64(&mut self) -> u64 {
+        dispatch!(self, rng => rng.next_u64())
+    }
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        dispatch!(self, rng => rng.fill_bytes(dst))
+    }
+}
+
+fn time_draws<R: RngCore + SeedableRng>(draws: usize) -> f64 {
+    let mut stream = SharedStream::<R>::new(0);
+    let start = Instant::now();
+    for _ in 0..draws {
+        black_box(stream.event_times(black_box(1.5), black_box(1.0)));
+        black_box(stream.index(black_box(100_000)));
+    }
+    start.elapsed().as_secs_f64() / draws as f64
+}
+
+// Seeds the stream the infectors are drawn from, which has to differ from the
+// run's own
+pub fn infector_seed(seed: u64) -> u64 {
+    seed ^ 0x9e37_79b9_7f4a_7c15
+}
+
+// The random draws the SIR loop makes. With `common_random_numbers` both
+// models take each decision from this stream in the same order:
+//
+// 1. Seeding: `index(population)` until a susceptible person comes up, once
+//    per initial infection
+// 2. Each event: `event_times`, then
+//    - infection: `index(population)` for the contact
+//    - recovery: `index(infectious)` for the person who recovers
+//
+// When tracking transmission, the infector of each infection is
+// `index(infectious)` from a second stream seeded with `infector_seed`, so
+// that tracking doesn't change the epidemic.
+//
+// where `index(infectious)` indexes the infectious people in the order they
+// were infected, with recoveries swap-removed. With `direct_sampling` the
+// contact is `index(susceptible)` instead, indexing the susceptible people in
+// the order they were added with infections swap-removed.
+#[derive(Serialize, Deserialize)]
+pub struct SharedStream<R = DefaultRng> {
+    rng: R,
+}
+
+impl<R: RngCore + SeedableRng> SharedStream<R> {
+    pub fn new(seed: u64) -> Self {
+        Self::from_rng(R::seed_from_u64(seed))
+    }
+}
+
+impl<R: RngCore> SharedStream<R> {
+    pub fn from_rng(rng: R) -> Self {
+        Self { rng }
+    }
+
+    // Waiting times until the next infection and the next recovery
+    pub fn event_times(&mut self, infection_rate: f64, recovery_rate: f64) -> (f64, f64) {
+        let infection = self.rng.sample(Exp::new(infection_rate).unwrap());
+        let recovery = self.rng.sample(Exp::new(recovery_rate).unwrap());
+        (infection, recovery)
+    }
+
+    // A uniform index into something of length n
+    pub fn index(&mut self, n: usize) -> usize {
+        self.rng.random_range(0..n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters};
+
+    #[test]
+    fn same_seed_same_draws() {
+        let mut a = SharedStream::<DefaultRng>::new(42);
+        let mut b = SharedStream::from_rng(RngKind::Small.seeded(42));
+        for _ in 0..100 {
+            assert_eq!(a.event_times(1.0, 0.5), b.event_times(1.0, 0.5));
+            assert_eq!(a.index(1000), b.index(1000));
+        }
+    }
+
+    #[test]
+    fn xoshiro_is_small() {
+        assert_eq!(
+            RngKind::from_str("xoshiro256-plus-plus", false),
+            Ok(RngKind::Small)
+        );
+        let kind: RngKind = serde_json::from_str(r#""xoshiro256-plus-plus""#).unwrap();
+        assert_eq!(kind, RngKind::Small);
+
+        // A generator saved under the old variant's name
+        let mut saved = serde_json::to_value(RngKind::Small.seeded(3)).unwrap();
+        saved = serde_json::json!({ "Xoshiro256PlusPlus": saved["Small"] });
+        let mut loaded: AnyRng = serde_json::from_value(saved).unwrap();
+        assert_eq!(loaded.next_u64(), RngKind::Small.seeded(3).next_u64());
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn matches_rand_generators() {
+        let mut small = DefaultRng::seed_from_u64(7);
+        let mut rand_small = rand::rngs::SmallRng::seed_from_u64(7);
+        let mut std = ChaCha12Rng::seed_from_u64(7);
+        let mut rand_std = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            assert_eq!(small.next_u64(), rand_small.next_u64());
+            assert_eq!(small.next_u32(), rand_small.next_u32());
+            assert_eq!(std.next_u64(), rand_std.next_u64());
+            assert_eq!(std.next_u32(), rand_std.next_u32());
+        }
+    }
+
+    fn check_identical_events(params: Parameters) {
+        let mut models: Vec<_> = ModelKind::all()
+            .into_iter()
+            .map(|kind| kind.into_model(params.clone()))
+            .collect();
+        for model in &mut models {
+            model.run().unwrap();
+        }
+
+        let (baseline, others) = models.split_first().unwrap();
+        let expected = baseline.get_transmission_tree().unwrap().infections();
+        assert!(expected.len() > 100);
+        for model in others {
+            let tree = model.get_transmission_tree().unwrap();
+            assert_eq!(tree.infections(), expected);
+
+            let (a, b) = (baseline.get_stats(), model.get_stats());
+            assert_eq!(
+                a.get_events().infection_attempts,
+                b.get_events().infection_attempts
+            );
+            assert_eq!(
+                a.get_events().rejected_contacts,
+                b.get_events().rejected_contacts
+            );
+            assert_eq!(a.get_events().recoveries, b.get_events().recoveries);
+            assert_eq!(a.get_peak_prevalence(), b.get_peak_prevalence());
+        }
+    }
+
+    #[test]
+    fn models_give_identical_events() {
+        let params = Parameters {
+            population: 2000,
+            seed: 99,
+            max_time: 1000.0,
+            track_transmission: true,
+            common_random_numbers: true,
+            ..Parameters::default()
+        };
+        check_identical_events(params.clone());
+        check_identical_events(Parameters {
+            direct_sampling: true,
+            ..params
+        });
+    }
+
+    #[test]
+    fn every_generator_is_shared() {
+        let mut final_sizes = Vec::new();
+        for rng in RngKind::all() {
+            let params = Parameters {
+                population: 500,
+                track_transmission: true,
+                common_random_numbers: true,
+                rng,
+                ..Parameters::default()
+            };
+            let mut baseline = ModelKind::Baseline.into_model(params.clone());
+            let mut ixa = ModelKind::Ixa.into_model(params);
+            baseline.run().unwrap();
+            ixa.run().unwrap();
+            assert_eq!(
+                baseline.get_transmission_tree().unwrap().infections(),
+                ixa.get_transmission_tree().unwrap().infections(),
+                "{rng:?}"
+            );
+            final_sizes.push(baseline.get_stats().get_cum_incidence());
+        }
+        // Different generators give different epidemics
+        final_sizes.dedup();
+        assert!(final_sizes.len() > 1);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    ModelKind,
+    base::{ParameterError, Parameters},
+    bench::run_benchmark,
+};
+use statrs::distribution::{ContinuousCDF, StudentsT};
+
+// Populations from `from` to `to` spaced evenly on a log scale, or None
+// unless there are at least two distinct ones to fit a line through
+pub fn populations(from: usize, to: usize, points: usize) -> Option<Vec<usize>> {
+    if points < 2 || from == 0 || from >= to {
+        return None;
+    }
+    let ratio = (to as f64 / from as f64).powf(1.0 / (points - 1) as f64);
+    let mut populations: Vec<usize> = (0..points)
+        .map(|i| (from as f64 * ratio.powi(i as i32)).round() as usize)
+        .collect();
+    populations.dedup();
+    Some(populations)
+}
+
+// A least-squares line through (ln x, ln y): y grows like x^exponent
+#[derive(Clone, Copy, Debug)]
+pub struct PowerLaw {
+    pub exponent: f64,
+    // 95% confidence interval of the exponent; needs at least three points
+    pub interval: Option<(f64, f64)>,
+}
+
+pub fn fit(x: &[f64], y: &[f64]) -> PowerLaw {
+    let n = x.len() as f64;
+    let lx: Vec<f64> = x.iter().map(|v| v.ln()).collect();
+    let ly: Vec<f64> = y.iter().map(|v| v.ln()).collect();
+    let mx = lx.iter().sum::<f64>() / n;
+    let my = ly.iter().sum::<f64>() / n;
+    let sxx: f64 = lx.iter().map(|x| (x - mx).powi(2)).sum();
+    let sxy: f64 = lx.iter().zip(&ly).map(|(x, y)| (x - mx) * (y - my)).sum();
+    let exponent = sxy / sxx;
+
+    let interval = (x.len() >= 3).then(|| {
+        let intercept = my - exponent * mx;
+        let sse: f64 = lx
+            .iter()
+            .zip(&ly)
+            .map(|(x, y)| (y - intercept - exponent * x).powi(2))
+            .sum();
+        let se = (sse / (n - 2.0) / sxx).sqrt();
+        let t = StudentsT::new(0.0, 1.0, n - 2.0)
+            .unwrap()
+            .inverse_cdf(0.975);
+        (exponent - t * se, exponent + t * se)
+    });
+    PowerLaw { exponent, interval }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScalingPoint {
+    pub population: usize,
+    // Of the epidemic loop, since setup is linear in the population
+    pub mean_seconds: f64,
+    // Infection attempts and recoveries; each timed run has the same seed,
+    // so the same events
+    pub events: usize,
+    pub cum_incidence: usize,
+}
+
+impl ScalingPoint {
+    pub fn seconds_per_event(&self) -> f64 {
+        self.mean_seconds / self.events as f64
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Scaling {
+    pub model: ModelKind,
+    pub points: Vec<ScalingPoint>,
+    pub runtime: PowerLaw,
+    pub per_event: PowerLaw,
+}
+
+impl Scaling {
+    // Whether the cost of an event grows with the population by more than
+    // `margin` in the exponent, beyond the noise when that can be told. The
+    // runtime also depends on how many people each run happens to infect, so
+    // the cost per event is the cleaner measure.
+    pub fn is_superlinear(&self, margin: f64) -> bool {
+        match self.per_event.interval {
+            Some((low, _)) => low > margin,
+            None => self.per_event.exponent > margin,
+        }
+    }
+}
+
+// Times the epidemic loop of `kind` at each population with otherwise the
+// same parameters, resetting one model between runs
+pub fn measure(
+    kind: ModelKind,
+    params: &Parameters,
+    populations: &[usize],
+    warmup: usize,
+    runs: usize,
+) -> Result<Scaling, ParameterError> {
+    let mut points = Vec::with_capacity(populations.len());
+    for &population in populations {
+        let params = Parameters {
+            population,
+            ..params.clone()
+        };
+        params.validate()?;
+        let result = run_benchmark("scaling", kind, &params, warmup, runs, true)?;
+        points.push(ScalingPoint {
+            population,
+            mean_seconds: result.mean(),
+            events: result.events.unwrap_or_default().max(1),
+            cum_incidence: result.cum_incidence.unwrap_or_default(),
+        });
+    }
+    let n: Vec<f64> = points.iter().map(|p| p.population as f64).collect();
+    let seconds: Vec<f64> = points.iter().map(|p| p.mean_seconds).collect();
+    let per_event: Vec<f64> = points.iter().map(|p| p.seconds_per_event()).collect();
+    Ok(Scaling {
+        model: kind,
+        runtime: fit(&n, &seconds),
+        per_event: fit(&n, &per_event),
+        points,
+    })
+}
+
+fn format_fit(fit: &PowerLaw) -> String {
+    match fit.interval {
+        Some((low, high)) => format!("N^{:.2} [{low:.2}, {high:.2}]", fit.exponent),
+        None => format!("N^{:.2}", fit.exponent),
+    }
+}
+
+pub fn print_scaling(results: &[Scaling], margin: f64) {
+    println!(
+        "{:<16} {:>10} {:>12} {:>10} {:>12} {:>9}",
+        "model", "population", "loop (s)", "events", "ns/event", "infected"
+    );
+    for result in results {
+        for p in &result.points {
+            println!(
+                "{:<16} {:>10} {:>12.5} {:>10} {:>12.1} {:>8.1}%",
+                result.model.name(),
+                p.population,
+                p.mean_seconds,
+                p.events,
+                p.seconds_per_event() * 1e9,
+                p.cum_incidence as f64 / p.population as f64 * 100.0
+            );
+        }
+    }
+    println!();
+    for result in results {
+        println!(
+            "{:<16} runtime ~ {}, per event ~ {}{}",
+            result.model.name(),
+            format_fit(&result.runtime),
+            format_fit(&result.per_event),
+            if result.is_superlinear(margin) {
+                "  SUPER-LINEAR"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use super::*;
+
+    #[test]
+    fn geometric_populations() {
+        assert_eq!(
+            populations(1000, 100_000, 5),
+            Some(vec![1000, 3162, 10_000, 31_623, 100_000])
+        );
+        assert_eq!(populations(1, 2, 5), Some(vec![1, 2]));
+        assert_eq!(populations(1000, 1000, 5), None);
+        assert_eq!(populations(1000, 100_000, 1), None);
+        assert_eq!(populations(0, 1000, 5), None);
+    }
+
+    #[test]
+    fn fits_power_laws() {
+        let x = [1e3, 1e4, 1e5, 1e6];
+        let exact = fit(&x, &x.map(|n: f64| 2e-6 * n.powf(1.5)));
+        assert_relative_eq!(exact.exponent, 1.5, epsilon = 1e-9);
+        let (low, high) = exact.interval.unwrap();
+        assert_relative_eq!(low, 1.5, epsilon = 1e-6);
+        assert_relative_eq!(high, 1.5, epsilon = 1e-6);
+
+        let noisy = fit(&x, &[1.1e-3, 0.9e-2, 1.05e-1, 0.95]);
+        let (low, high) = noisy.interval.unwrap();
+        assert!(low < 1.0 && 1.0 < high && high - low < 0.2);
+    }
+
+    #[test]
+    fn baseline_events_grow_with_the_population() {
+        let params = Parameters {
+            r0: 1.5,
+            infectious_period: 3.0,
+            ..Parameters::default()
+        };
+        let scaling = measure(
+            ModelKind::Baseline,
+            &params,
+            &populations(2000, 32_000, 3).unwrap(),
+            1,
+            3,
+        )
+        .unwrap();
+        assert_eq!(scaling.points.len(), 3);
+        // Only the events are checked, since timings are too noisy for a test
+        let events: Vec<f64> = scaling.points.iter().map(|p| p.events as f64).collect();
+        let populations: Vec<f64> = scaling.points.iter().map(|p| p.population as f64).collect();
+        assert_relative_eq!(fit(&populations, &events).exponent, 1.0, epsilon = 0.1);
+    }
+}
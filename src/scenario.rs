@@ -0,0 +1,41 @@
+use crate::base::{Parameters, ParametersBuilder};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// The scenarios the binaries run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scenario {
+    // Population of 1000
+    Sir,
+    // Same as `Sir` with a population of 100k
+    Large,
+}
+
+impl Scenario {
+    pub fn all() -> [Scenario; 2] {
+        [Scenario::Sir, Scenario::Large]
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::Sir => "sir",
+            Scenario::Large => "large",
+        }
+    }
+    // The scenario as the binaries run it without options
+    pub fn builder(self) -> ParametersBuilder {
+        let builder = Parameters::builder()
+            .r0(1.5)
+            .infectious_period(3.0)
+            .initial_infections(5)
+            .max_time(200.0)
+            .disable_queries(false);
+        match self {
+            Scenario::Sir => builder.population(1000).seed(1234),
+            Scenario::Large => builder.population(100_000).seed(12345),
+        }
+    }
+    pub fn params(self) -> Parameters {
+        self.builder().build().unwrap()
+    }
+}
@@ -1,159 +1,498 @@
 use crate::{
-    base::{Parameters, SIRModel},
-    stats::ModelStats,
+    base::{ParameterError, Parameters, SIRModel},
+    checkpoint::{CheckpointError, Checkpoints, ModelState, Status},
+    invariants::{CheckedEvent, InvariantChecker, InvariantViolation},
+    observer::{Observers, SimulationObserver},
+    rng::{DefaultRng, RngKind, SharedStream, infector_seed},
+    stats::{EventKind, ModelStats},
+    stop::StopReason,
+    trace::Trace,
+    transmission::TransmissionTree,
 };
-use indexmap::IndexSet;
-use rand::{Rng, SeedableRng, rngs::SmallRng};
-use rand_distr::Exp;
-
-#[derive(Clone, Copy)]
-pub enum InfectionStatus {
-    Susceptible,
-    Infectious,
-    Recovered,
-}
 
-pub struct Context {
+use super::store::{
+    BitsetStore, CompactSoaStore, IndexSetStore, PopulationStore, StoreKind, VecPositionsStore,
+};
+pub use super::store::{InfectionStatus, PersonId};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::{ChaCha8Rng, ChaCha12Rng};
+use rand_pcg::Pcg64;
+use serde::{Serialize, de::DeserializeOwned};
+use std::time::Instant;
+
+// Generic over the generator and the population's storage so that their
+// costs can be compared without dynamic dispatch; `ModelKind::into_model`
+// picks them from `Parameters::rng` and `Parameters::store`
+pub struct Context<R = DefaultRng, S = IndexSetStore> {
     parameters: Parameters,
     time: f64,
-    rng: SmallRng,
-    infection_status_lookup: Vec<InfectionStatus>,
-    susceptible_people: IndexSet<PersonId>,
-    infectious_people: IndexSet<PersonId>,
-    recovered_people: IndexSet<PersonId>,
-    population: usize,
+    rng: SharedStream<R>,
+    // Only when tracking transmission
+    infector_rng: Option<SharedStream<R>>,
+    people: S,
     stats: ModelStats,
+    transmission_tree: Option<TransmissionTree>,
+    trace: Option<Trace>,
+    checkpoints: Option<Checkpoints>,
+    observers: Observers,
+    stop_reason: Option<StopReason>,
+    // Only while simulating with `check_invariants`
+    invariants: Option<InvariantChecker>,
+    // The first inconsistency the checks found, which ended the simulation
+    violation: Option<InvariantViolation>,
+}
+
+// The generators the baseline can be built with
+pub trait ModelRng: RngCore + SeedableRng + Serialize + DeserializeOwned + 'static {}
+impl<R: RngCore + SeedableRng + Serialize + DeserializeOwned + 'static> ModelRng for R {}
+
+// The baseline with the storage `params.store` and the generator
+// `params.rng` pick
+pub fn new_model(params: Parameters) -> Box<dyn SIRModel> {
+    match params.store {
+        StoreKind::IndexSet => new_model_with::<IndexSetStore>(params),
+        StoreKind::VecPositions => new_model_with::<VecPositionsStore>(params),
+        StoreKind::Bitset => new_model_with::<BitsetStore>(params),
+        StoreKind::CompactSoa => new_model_with::<CompactSoaStore>(params),
+    }
+}
+
+fn new_model_with<S: PopulationStore>(params: Parameters) -> Box<dyn SIRModel> {
+    match params.rng {
+        RngKind::Small => Box::new(Context::<DefaultRng, S>::new(params)),
+        RngKind::Std => Box::new(Context::<ChaCha12Rng, S>::new(params)),
+        RngKind::Chacha8 => Box::new(Context::<ChaCha8Rng, S>::new(params)),
+        RngKind::Pcg64 => Box::new(Context::<Pcg64, S>::new(params)),
+    }
+}
+
+pub fn restore_model(state: ModelState) -> Result<Box<dyn SIRModel>, CheckpointError> {
+    match state.params.store {
+        StoreKind::IndexSet => restore_model_with::<IndexSetStore>(state),
+        StoreKind::VecPositions => restore_model_with::<VecPositionsStore>(state),
+        StoreKind::Bitset => restore_model_with::<BitsetStore>(state),
+        StoreKind::CompactSoa => restore_model_with::<CompactSoaStore>(state),
+    }
+}
+
+fn restore_model_with<S: PopulationStore>(
+    state: ModelState,
+) -> Result<Box<dyn SIRModel>, CheckpointError> {
+    Ok(match state.params.rng {
+        RngKind::Small => Box::new(Context::<DefaultRng, S>::restore(state)?),
+        RngKind::Std => Box::new(Context::<ChaCha12Rng, S>::restore(state)?),
+        RngKind::Chacha8 => Box::new(Context::<ChaCha8Rng, S>::restore(state)?),
+        RngKind::Pcg64 => Box::new(Context::<Pcg64, S>::restore(state)?),
+    })
 }
 
-impl Context {
-    pub fn new(parameters: Parameters) -> Context {
-        let stats = ModelStats::new(0);
+fn new_stats(parameters: &Parameters) -> ModelStats {
+    let mut stats = ModelStats::new(0);
+    if parameters.profile_events {
+        stats.enable_profiling();
+    }
+    stats
+}
+
+impl<R: ModelRng, S: PopulationStore> Context<R, S> {
+    pub fn new(parameters: Parameters) -> Self {
+        let stats = new_stats(&parameters);
         Context {
-            infection_status_lookup: Vec::new(),
-            susceptible_people: IndexSet::new(),
-            infectious_people: IndexSet::new(),
-            recovered_people: IndexSet::new(),
-            population: 0,
-            rng: SmallRng::seed_from_u64(parameters.seed),
-            parameters,
+            people: S::default(),
+            rng: SharedStream::new(parameters.seed),
+            infector_rng: parameters
+                .track_transmission
+                .then(|| SharedStream::new(infector_seed(parameters.seed))),
             time: 0.0,
             stats,
+            transmission_tree: parameters.track_transmission.then(TransmissionTree::new),
+            trace: parameters.trace.then(Trace::new),
+            checkpoints: None,
+            observers: Observers::default(),
+            stop_reason: None,
+            invariants: None,
+            violation: None,
+            parameters,
         }
     }
 
-    fn add_person(&mut self, infection_status: InfectionStatus) -> PersonId {
-        self.infection_status_lookup.push(infection_status);
-        let person_id = PersonId {
-            id: self.population,
-        };
-        self.population += 1;
-        match infection_status {
-            InfectionStatus::Susceptible => {
-                self.susceptible_people.insert(person_id);
-            }
-            InfectionStatus::Infectious => {
-                self.infectious_people.insert(person_id);
-            }
-            InfectionStatus::Recovered => {
-                self.recovered_people.insert(person_id);
-            }
+    // Continues from a checkpoint instead of setting up
+    pub fn restore(state: ModelState) -> Result<Self, CheckpointError> {
+        state.validate()?;
+        let susceptible = state.susceptible_order();
+        let mut context = Self::new(state.params);
+        context.time = state.time;
+        context.rng = serde_json::from_value(state.rng)?;
+        if let Some(rng) = state.infector_rng {
+            context.infector_rng = Some(serde_json::from_value(rng)?);
+        }
+        // Everyone is added as recovered, then the others are moved in the
+        // saved orders, which sampling depends on
+        for _ in 0..state.statuses.len() {
+            context.people.add_person(InfectionStatus::Recovered);
         }
-        person_id
+        for id in susceptible {
+            context
+                .people
+                .set_status(PersonId { id }, InfectionStatus::Susceptible);
+        }
+        for id in state.infectious {
+            context
+                .people
+                .set_status(PersonId { id }, InfectionStatus::Infectious);
+        }
+        context.stats = state.stats;
+        context.transmission_tree = state.transmission_tree;
+        Ok(context)
+    }
+
+    pub fn checkpoint(&self) -> ModelState {
+        ModelState {
+            params: self.parameters.clone(),
+            time: self.time,
+            rng: serde_json::to_value(&self.rng).unwrap(),
+            infector_rng: self
+                .infector_rng
+                .as_ref()
+                .map(|rng| serde_json::to_value(rng).unwrap()),
+            statuses: (0..self.people.population())
+                .map(|id| match self.people.status(PersonId { id }) {
+                    InfectionStatus::Susceptible => Status::Susceptible,
+                    InfectionStatus::Infectious => Status::Infectious,
+                    InfectionStatus::Recovered => Status::Recovered,
+                })
+                .collect(),
+            infectious: (0..self.people.count(InfectionStatus::Infectious))
+                .map(|i| self.people.nth(InfectionStatus::Infectious, i).id)
+                .collect(),
+            susceptible: (0..self.people.count(InfectionStatus::Susceptible))
+                .map(|i| self.people.nth(InfectionStatus::Susceptible, i).id)
+                .collect(),
+            stats: self.stats.clone(),
+            transmission_tree: self.transmission_tree.clone(),
+        }
+    }
+
+    fn save_checkpoints(&mut self) {
+        if self
+            .checkpoints
+            .as_ref()
+            .is_some_and(|checkpoints| checkpoints.due(self.time))
+        {
+            let state = self.checkpoint();
+            self.checkpoints.as_mut().unwrap().save(&state);
+        }
+    }
+
+    fn add_person(&mut self, infection_status: InfectionStatus) -> PersonId {
+        self.people.add_person(infection_status)
     }
 
     fn get_infection_status(&self, person_id: PersonId) -> InfectionStatus {
-        *self.infection_status_lookup.get(person_id.id).unwrap()
+        self.people.status(person_id)
     }
 
     fn set_infection_status(&mut self, person_id: PersonId, infection_status: InfectionStatus) {
-        match infection_status {
-            InfectionStatus::Susceptible => {
-                self.susceptible_people.insert(person_id);
-            }
-            InfectionStatus::Infectious => {
-                self.susceptible_people.swap_remove(&person_id);
-                self.infectious_people.insert(person_id);
-            }
-            InfectionStatus::Recovered => {
-                self.infectious_people.swap_remove(&person_id);
-                self.recovered_people.insert(person_id);
-            }
+        self.people.set_status(person_id, infection_status);
+    }
+
+    fn seed_infection(&mut self, person_id: PersonId) {
+        self.set_infection_status(person_id, InfectionStatus::Infectious);
+        if let Some(tree) = &mut self.transmission_tree {
+            tree.record_seed(person_id.id, self.time);
         }
-        *self.infection_status_lookup.get_mut(person_id.id).unwrap() = infection_status;
     }
 
-    fn infect_person(&mut self, person_id: PersonId, _t: Option<f64>) {
+    fn infect_person(&mut self, person_id: PersonId, infector: Option<PersonId>, t: f64) {
         self.set_infection_status(person_id, InfectionStatus::Infectious);
         self.stats.record_infection();
+
+        if let (Some(tree), Some(infector)) = (&mut self.transmission_tree, infector) {
+            tree.record_infection(person_id.id, infector.id, t);
+        }
+        self.observers
+            .infection(t, person_id.id, infector.map(|p| p.id));
+        if let Some(trace) = &mut self.trace {
+            trace.event(t);
+        }
     }
 
     fn sample_random_person(&mut self) -> PersonId {
-        let index = self.rng.random_range(0..self.population);
+        let index = self.rng.index(self.people.population());
         PersonId { id: index }
     }
 
+    fn sample_susceptible_person(&mut self) -> PersonId {
+        let index = self
+            .rng
+            .index(self.people.count(InfectionStatus::Susceptible));
+        self.people.nth(InfectionStatus::Susceptible, index)
+    }
+
+    // Every infectious person contacts others at the same rate, so the
+    // infector is a uniform draw from the infectious people. It comes from
+    // its own stream, so that tracking transmission doesn't change the epidemic.
+    fn sample_infector(&mut self) -> Option<PersonId> {
+        let n = self.people.count(InfectionStatus::Infectious);
+        let index = self.infector_rng.as_mut()?.index(n);
+        Some(self.people.nth(InfectionStatus::Infectious, index))
+    }
+
+    fn sample_infectious_person(&mut self) -> PersonId {
+        let index = self
+            .rng
+            .index(self.people.count(InfectionStatus::Infectious));
+        self.people.nth(InfectionStatus::Infectious, index)
+    }
+
     pub fn get_stats(&self) -> &ModelStats {
         &self.stats
     }
 
-    pub fn run(&mut self) {
+    pub fn get_transmission_tree(&self) -> Option<&TransmissionTree> {
+        self.transmission_tree.as_ref()
+    }
+
+    pub fn get_trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    fn start_span(&self) -> Option<Instant> {
+        self.trace.as_ref().map(Trace::start_span)
+    }
+
+    fn record_span(&mut self, name: &'static str, cat: &'static str, start: Option<Instant>) {
+        if let (Some(trace), Some(start)) = (&mut self.trace, start) {
+            trace.record_span(name, cat, start);
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), ParameterError> {
+        self.setup()?;
+        self.simulate();
+        Ok(())
+    }
+
+    pub fn setup(&mut self) -> Result<(), ParameterError> {
+        self.parameters.validate()?;
+
         // Set up population
+        let span = self.start_span();
         for _ in 0..self.parameters.population {
             self.add_person(InfectionStatus::Susceptible);
         }
+        self.record_span("setup", "setup", span);
 
         // Seed infections
+        let span = self.start_span();
         for _ in 0..self.parameters.initial_infections {
-            let n_susceptible = self.susceptible_people.len();
-            let index = self.rng.random_range(0..n_susceptible);
-            let person_to_infect = *self.susceptible_people.get_index(index).unwrap();
-            self.infect_person(person_to_infect, None);
+            let person_to_infect = if self.parameters.common_random_numbers {
+                // ixa can't index our susceptible set, so draw from everyone
+                loop {
+                    let person = self.sample_random_person();
+                    if let InfectionStatus::Susceptible = self.get_infection_status(person) {
+                        break person;
+                    }
+                }
+            } else {
+                let n_susceptible = self.people.count(InfectionStatus::Susceptible);
+                let index = self.rng.index(n_susceptible);
+                self.people.nth(InfectionStatus::Susceptible, index)
+            };
+            self.seed_infection(person_to_infect);
+        }
+        // Like ixa, seeded infections count towards prevalence but not incidence
+        self.stats
+            .set_prevalence(self.parameters.initial_infections);
+        self.record_span("seeding", "setup", span);
+        Ok(())
+    }
+
+    // Returns to the state just after setup with a new seed. The population
+    // is rebuilt in the storage of the last run, so it doesn't allocate. The
+    // trace starts again, so that it only covers the run that follows.
+    pub fn reset(&mut self, seed: u64) -> Result<(), ParameterError> {
+        self.parameters.seed = seed;
+        self.rng = SharedStream::new(seed);
+        if let Some(rng) = &mut self.infector_rng {
+            *rng = SharedStream::new(infector_seed(seed));
+        }
+        self.time = 0.0;
+        self.stats = new_stats(&self.parameters);
+        if let Some(tree) = &mut self.transmission_tree {
+            tree.clear();
+        }
+        if let Some(trace) = &mut self.trace {
+            *trace = Trace::new();
         }
+        self.stop_reason = None;
+        self.violation = None;
+        self.observers.reset();
+        self.people.clear();
+        self.setup()
+    }
+
+    fn check_invariants(&mut self, event: CheckedEvent) -> Result<(), InvariantViolation> {
+        let Some(mut checker) = self.invariants.take() else {
+            return Ok(());
+        };
+        let result = checker
+            .event(self.time, event)
+            .and_then(|()| self.check_state(&checker, event));
+        self.invariants = Some(checker);
+        result
+    }
+
+    fn check_state(
+        &self,
+        checker: &InvariantChecker,
+        event: CheckedEvent,
+    ) -> Result<(), InvariantViolation> {
+        let (s, i, r) = (
+            self.people.count(InfectionStatus::Susceptible),
+            self.people.count(InfectionStatus::Infectious),
+            self.people.count(InfectionStatus::Recovered),
+        );
+        let population = self.people.population();
+        checker.ensure(s + i + r == population, || {
+            format!("S + I + R = {s} + {i} + {r} for a population of {population}")
+        })?;
+        checker.check_stats(&self.stats, self.parameters.initial_infections, i, Some(r))?;
+
+        let (id, expected) = match event {
+            CheckedEvent::Infection(id) => (id, InfectionStatus::Infectious),
+            CheckedEvent::Recovery(id) => (id, InfectionStatus::Recovered),
+            CheckedEvent::RejectedContact(id) => (id, self.people.status(PersonId { id })),
+        };
+        let person = PersonId { id };
+        let status = self.people.status(person);
+        let in_sets = (
+            self.people.contains(InfectionStatus::Susceptible, person),
+            self.people.contains(InfectionStatus::Infectious, person),
+            self.people.contains(InfectionStatus::Recovered, person),
+        );
+        let expected_sets = (
+            status == InfectionStatus::Susceptible,
+            status == InfectionStatus::Infectious,
+            status == InfectionStatus::Recovered,
+        );
+        checker.ensure(
+            status == expected
+                && status != InfectionStatus::Susceptible
+                && in_sets == expected_sets,
+            || format!("person {id} is {status:?} and in the (S, I, R) sets {in_sets:?}"),
+        )
+    }
 
+    pub fn simulate(&mut self) {
         // Start infection loop
         let infection_rate = self.parameters.r0 / self.parameters.infectious_period;
-        let mut n_infectious = self.infectious_people.len();
+        let mut n_infectious = self.people.count(InfectionStatus::Infectious);
+        let start = Instant::now();
+        self.stop_reason = None;
+        self.violation = None;
+        self.invariants = self
+            .parameters
+            .check_invariants
+            .then(|| InvariantChecker::new(self.time));
+        let span = self.start_span();
 
         while n_infectious > 0 && self.time < self.parameters.max_time {
-            let infection_event_rate = infection_rate * (n_infectious as f64);
+            let timer = self.stats.start_timer();
+            let mut infection_event_rate = infection_rate * (n_infectious as f64);
+            if self.parameters.direct_sampling {
+                // Only contacts with susceptible people, which all infect
+                infection_event_rate *= self.people.count(InfectionStatus::Susceptible) as f64
+                    / self.people.population() as f64;
+            }
             let recovery_event_rate = (n_infectious as f64) / self.parameters.infectious_period;
 
-            let infection_event_time = self.rng.sample(Exp::new(infection_event_rate).unwrap());
-            let recovery_event_time = self.rng.sample(Exp::new(recovery_event_rate).unwrap());
+            let (infection_event_time, recovery_event_time) = self
+                .rng
+                .event_times(infection_event_rate, recovery_event_rate);
 
-            if infection_event_time < recovery_event_time {
-                let person_to_infect = self.sample_random_person();
+            let event = if infection_event_time < recovery_event_time {
+                self.stats.record_infection_attempt();
+                let person_to_infect = if self.parameters.direct_sampling {
+                    self.sample_susceptible_person()
+                } else {
+                    self.sample_random_person()
+                };
                 if let InfectionStatus::Susceptible = self.get_infection_status(person_to_infect) {
+                    self.stats.record_time(EventKind::Sampling, timer);
+                    let timer = self.stats.start_timer();
                     self.time += infection_event_time;
-                    self.infect_person(person_to_infect, Some(self.time));
+                    let infector = self.sample_infector();
+                    self.infect_person(person_to_infect, infector, self.time);
+                    self.stats.record_time(EventKind::Infection, timer);
+                    CheckedEvent::Infection(person_to_infect.id)
+                } else {
+                    // Nobody was infected, but the time until the attempt still passed
+                    self.time += infection_event_time;
+                    self.stats.record_rejected_contact();
+                    self.stats.record_time(EventKind::Sampling, timer);
+                    CheckedEvent::RejectedContact(person_to_infect.id)
                 }
             } else {
-                let index = self.rng.random_range(0..n_infectious);
-                let person_to_recover = *self.infectious_people.get_index(index).unwrap();
+                self.stats.record_time(EventKind::Sampling, timer);
+                let timer = self.stats.start_timer();
+                let person_to_recover = self.sample_infectious_person();
                 self.set_infection_status(person_to_recover, InfectionStatus::Recovered);
                 self.stats.record_recovery();
                 self.time += recovery_event_time;
+                self.observers.recovery(self.time, person_to_recover.id);
+                if let Some(trace) = &mut self.trace {
+                    trace.event(self.time);
+                }
+                self.stats.record_time(EventKind::Recovery, timer);
+                CheckedEvent::Recovery(person_to_recover.id)
+            };
+            if let Err(violation) = self.check_invariants(event) {
+                self.violation = Some(violation);
+                self.stop_reason = Some(StopReason::Invariant);
+                break;
             }
 
-            n_infectious = self.infectious_people.len();
+            n_infectious = self.people.count(InfectionStatus::Infectious);
+            if n_infectious > 0 {
+                self.save_checkpoints();
+            }
+            // Like ixa, which can only stop between status changes while
+            // someone is infectious
+            if !matches!(event, CheckedEvent::RejectedContact(_)) && n_infectious > 0 {
+                self.stop_reason = self.parameters.stop.check(&self.stats, start);
+                if self.stop_reason.is_some() {
+                    break;
+                }
+            }
         }
+        if self.stop_reason.is_none() {
+            self.stop_reason = Some(if n_infectious == 0 {
+                StopReason::Extinction
+            } else {
+                StopReason::MaxTime
+            });
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.finish(self.time);
+        }
+        self.record_span("simulate", "simulate", span);
+        self.observers.finish(self.time, &self.stats);
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub struct PersonId {
-    id: usize,
-}
-
-impl SIRModel for Context {
+impl<R: ModelRng, S: PopulationStore> SIRModel for Context<R, S> {
     fn id(&self) -> &'static str {
         "baseline"
     }
     fn current_time(&self) -> f64 {
         self.time
     }
-    fn run(&mut self) {
-        self.run();
+    fn setup(&mut self) -> Result<(), ParameterError> {
+        self.setup()
+    }
+    fn simulate(&mut self) {
+        self.simulate();
     }
     fn get_stats(&self) -> &ModelStats {
         self.get_stats()
@@ -161,6 +500,31 @@ impl SIRModel for Context {
     fn get_params(&self) -> &Parameters {
         &self.parameters
     }
+    fn get_transmission_tree(&self) -> Option<&TransmissionTree> {
+        self.get_transmission_tree()
+    }
+    fn get_trace(&self) -> Option<&Trace> {
+        self.get_trace()
+    }
+    fn reset(&mut self, seed: u64) -> Result<(), ParameterError> {
+        self.reset(seed)
+    }
+    fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+    fn invariant_violation(&self) -> Option<&InvariantViolation> {
+        self.violation.as_ref()
+    }
+    fn add_observer(&mut self, observer: Box<dyn SimulationObserver>) {
+        self.observers.add(observer);
+    }
+    fn set_checkpoints(&mut self, checkpoints: Checkpoints) -> Result<(), CheckpointError> {
+        self.checkpoints = Some(checkpoints);
+        Ok(())
+    }
+    fn checkpoint(&self) -> Result<ModelState, CheckpointError> {
+        Ok(self.checkpoint())
+    }
 }
 
 #[cfg(test)]
@@ -168,10 +532,12 @@ mod test {
     use approx::assert_relative_eq;
 
     use super::*;
+    use crate::rng::RngKind;
+    use crate::stop::StopConditions;
 
     #[test]
     fn run_model() {
-        let mut context = Context::new(Parameters {
+        let mut context = Context::<DefaultRng>::new(Parameters {
             r0: 1.5,
             infectious_period: 3.0,
             population: 100_000,
@@ -180,12 +546,85 @@ mod test {
             max_time: 200.0,
             enable_stats: true,
             disable_queries: true,
+            track_transmission: false,
+            profile_events: false,
+            common_random_numbers: false,
+            rng: RngKind::Small,
+            noop_observers: 0,
+            stop: StopConditions::default(),
+            check_invariants: false,
+            trace: false,
+            infected_set: false,
+            store: StoreKind::IndexSet,
+            direct_sampling: false,
         });
-        context.run();
+        context.run().unwrap();
 
         // Final size relation is ~58%
         let incidence = context.get_stats().get_cum_incidence() as f64;
         let expected = context.parameters.population as f64 * 0.58;
         assert_relative_eq!(incidence, expected, max_relative = 0.02);
     }
+
+    #[test]
+    fn direct_sampling_skips_rejected_contacts() {
+        let params = Parameters {
+            population: 10_000,
+            check_invariants: true,
+            ..Parameters::default()
+        };
+        let mut rejection = Context::<DefaultRng>::new(params.clone());
+        let mut direct = Context::<DefaultRng>::new(Parameters {
+            direct_sampling: true,
+            ..params
+        });
+        rejection.run().unwrap();
+        direct.run().unwrap();
+
+        // Late in the epidemic most contacts are with people who have had it
+        let events = rejection.get_stats().get_events();
+        assert!(events.infection_attempts as f64 > 1.3 * events.infections as f64);
+        let events = direct.get_stats().get_events();
+        assert!(events.infections > 1000);
+        assert_eq!(events.infection_attempts, events.infections);
+        assert_eq!(events.rejected_contacts, 0);
+    }
+
+    #[test]
+    fn detects_broken_invariants() {
+        let mut context = Context::<DefaultRng>::new(Parameters::default());
+        context.setup().unwrap();
+        context.invariants = Some(InvariantChecker::new(0.0));
+        let seed = context.people.nth(InfectionStatus::Infectious, 0);
+        assert!(
+            context
+                .check_invariants(CheckedEvent::RejectedContact(seed.id))
+                .is_ok()
+        );
+
+        // A recovery that skipped the sets
+        context.people.statuses[seed.id] = InfectionStatus::Recovered;
+        context.stats.record_recovery();
+        let err = context
+            .check_invariants(CheckedEvent::Recovery(seed.id))
+            .unwrap_err();
+        assert_eq!(err.event, 2);
+        assert_eq!(err.after, CheckedEvent::Recovery(seed.id));
+        assert!(err.message.contains("prevalence is 4 but 5"), "{err}");
+    }
+
+    #[test]
+    fn stops_at_the_first_violation() {
+        let mut context = Context::<DefaultRng>::new(Parameters {
+            check_invariants: true,
+            ..Parameters::default()
+        });
+        context.setup().unwrap();
+        // A recovery that nobody made
+        context.stats.record_recovery();
+        context.simulate();
+        assert_eq!(context.stop_reason, Some(StopReason::Invariant));
+        let violation = context.invariant_violation().unwrap();
+        assert_eq!(violation.event, 1);
+    }
 }
@@ -1,10 +1,19 @@
 use crate::{
-    base::{Parameters, SIRModel},
-    stats::ModelStats,
+    base::{ParameterError, Parameters, SIRModel},
+    checkpoint::{CheckpointError, Checkpoints, ModelState, Status},
+    invariants::{CheckedEvent, InvariantChecker, InvariantViolation},
+    observer::{Observers, SimulationObserver},
+    rng::{AnyRng, SharedStream, infector_seed},
+    stats::{EventKind, ModelStats},
+    stop::StopReason,
+    trace::Trace,
+    transmission::TransmissionTree,
 };
-use ixa::{PersonId, prelude::*};
+use indexmap::IndexSet;
+use ixa::{HashMap, PersonId, plan::PlanId, prelude::*};
 use serde::Serialize;
 use statrs::distribution::Exp;
+use std::time::Instant;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
 pub enum InfectionStatusValue {
@@ -23,13 +32,42 @@ define_global_property!(Params, Parameters);
 
 pub struct Model {
     ctx: Context,
+    // Restored from a checkpoint, so the next event is already planned
+    resumed: bool,
 }
 
 // TODO split up
 define_rng!(ModelRng);
+// Infectors only, so that tracking transmission doesn't change the epidemic
+define_rng!(InfectorRng);
 
 define_data_plugin!(ModelStatsPlugin, ModelStats, ModelStats::new(0));
 define_data_plugin!(InfectedPeoplePlugin, Vec<PersonId>, Vec::new());
+// Replaces InfectedPeoplePlugin with `infected_set`
+define_data_plugin!(InfectedSetPlugin, Option<IndexSet<PersonId>>, None);
+// The susceptible people for `direct_sampling`, even with queries, which
+// would have to scan them for every contact
+define_data_plugin!(SusceptibleSetPlugin, Option<IndexSet<PersonId>>, None);
+define_data_plugin!(TransmissionTreePlugin, Option<TransmissionTree>, None);
+// Only used with common random numbers, which bypass ModelRng
+define_data_plugin!(SharedStreamPlugin, Option<SharedStream<AnyRng>>, None);
+// Likewise, for the infectors when tracking transmission
+define_data_plugin!(InfectorStreamPlugin, Option<SharedStream<AnyRng>>, None);
+define_data_plugin!(PopulationPlugin, Vec<PersonId>, Vec::new());
+// Each person's index in the population, from the order they were added
+define_data_plugin!(PersonIndexPlugin, HashMap<PersonId, usize>, HashMap::default());
+define_data_plugin!(ShutdownPlanPlugin, Option<PlanId>, None);
+define_data_plugin!(CheckpointPlugin, Option<Checkpoints>, None);
+define_data_plugin!(ObserversPlugin, Observers, Observers::default());
+// When the current simulation started, for the wall clock budget
+define_data_plugin!(LoopStartPlugin, Option<Instant>, None);
+define_data_plugin!(StopReasonPlugin, Option<StopReason>, None);
+// Only while simulating with `check_invariants`
+define_data_plugin!(InvariantPlugin, Option<InvariantChecker>, None);
+// The first inconsistency the checks found, which ended the simulation
+define_data_plugin!(ViolationPlugin, Option<InvariantViolation>, None);
+define_data_plugin!(TracePlugin, Option<Trace>, None);
+define_data_plugin!(ReportPrefixPlugin, String, "ixa-".to_string());
 
 #[derive(Serialize)]
 pub struct Incidence {
@@ -42,13 +80,44 @@ define_report!(Incidence);
 trait InfectionLoop {
     fn get_params(&self) -> &Parameters;
     fn get_stats(&self) -> &ModelStats;
-    fn infected_people(&self) -> usize;
+    fn add_indexed_person(&mut self) -> PersonId;
+    fn person_index(&self, p: PersonId) -> usize;
+    fn infected_people(&mut self) -> usize;
+    fn infected_len(&self) -> usize;
+    fn infected_at(&self, index: usize) -> PersonId;
+    fn is_listed_infected(&self, p: PersonId) -> bool;
+    fn add_infected(&mut self, p: PersonId);
+    fn remove_infected(&mut self, p: PersonId);
+    fn common_random_numbers(&self) -> bool;
+    fn stream(&mut self) -> &mut SharedStream<AnyRng>;
+    fn event_times(&mut self, infection_rate: f64, recovery_rate: f64) -> (f64, f64);
     fn random_person(&mut self) -> Option<PersonId>;
     fn random_infected_person(&mut self) -> Option<PersonId>;
-    fn infect_person(&mut self, p: PersonId, t: Option<f64>);
+    fn susceptible_people(&self) -> usize;
+    fn random_susceptible_person(&mut self) -> Option<PersonId>;
+    fn random_contact(&mut self) -> Option<PersonId>;
+    fn sample_infector(&mut self) -> Option<PersonId>;
+    fn infect_person(&mut self, p: PersonId, infector: Option<PersonId>, t: Option<f64>);
     fn recover_person(&mut self, p: PersonId, t: f64);
     fn next_event(&mut self);
+    fn start_span(&self) -> Option<Instant>;
+    fn record_span(&mut self, name: &'static str, cat: &'static str, start: Option<Instant>);
+    fn record_call(&mut self, name: &'static str, cat: &'static str, start: Option<Instant>);
+    fn check_invariants(&mut self, t: f64, p: PersonId, event: fn(usize) -> CheckedEvent) -> bool;
+    fn check_invariants_with(
+        &self,
+        checker: &mut InvariantChecker,
+        t: f64,
+        p: PersonId,
+        event: CheckedEvent,
+    ) -> Result<(), InvariantViolation>;
+    fn continue_epidemic(&mut self);
+    fn end_epidemic(&mut self);
+    fn save_checkpoints(&mut self);
+    fn init(&mut self);
     fn setup(&mut self);
+    fn state(&self) -> Result<ModelState, CheckpointError>;
+    fn restore(&mut self, state: ModelState) -> Result<(), CheckpointError>;
 }
 
 impl InfectionLoop for Context {
@@ -58,52 +127,221 @@ impl InfectionLoop for Context {
     fn get_stats(&self) -> &ModelStats {
         self.get_data(ModelStatsPlugin)
     }
-    fn infected_people(&self) -> usize {
+    fn add_indexed_person(&mut self) -> PersonId {
+        let p = self.add_person(()).unwrap();
+        let people = self.get_data_mut(PersonIndexPlugin);
+        let index = people.len();
+        people.insert(p, index);
+        p
+    }
+    // ixa doesn't expose the index behind a PersonId
+    fn person_index(&self, p: PersonId) -> usize {
+        self.get_data(PersonIndexPlugin)[&p]
+    }
+    fn infected_people(&mut self) -> usize {
         if self.get_params().disable_queries {
-            self.get_data(InfectedPeoplePlugin).len()
+            self.infected_len()
+        } else {
+            self.get_data_mut(ModelStatsPlugin).record_query();
+            let span = self.start_span();
+            let n = self
+                .query_people((InfectionStatus, InfectionStatusValue::Infectious))
+                .len();
+            self.record_call("query_people", "query", span);
+            n
+        }
+    }
+    fn infected_len(&self) -> usize {
+        match self.get_data(InfectedSetPlugin) {
+            Some(set) => set.len(),
+            None => self.get_data(InfectedPeoplePlugin).len(),
+        }
+    }
+    fn infected_at(&self, index: usize) -> PersonId {
+        match self.get_data(InfectedSetPlugin) {
+            Some(set) => set[index],
+            None => self.get_data(InfectedPeoplePlugin)[index],
+        }
+    }
+    fn is_listed_infected(&self, p: PersonId) -> bool {
+        match self.get_data(InfectedSetPlugin) {
+            Some(set) => set.contains(&p),
+            None => self.get_data(InfectedPeoplePlugin).contains(&p),
+        }
+    }
+    fn add_infected(&mut self, p: PersonId) {
+        match self.get_data_mut(InfectedSetPlugin) {
+            Some(set) => {
+                set.insert(p);
+            }
+            None => self.get_data_mut(InfectedPeoplePlugin).push(p),
+        }
+    }
+    fn remove_infected(&mut self, p: PersonId) {
+        if let Some(set) = self.get_data_mut(InfectedSetPlugin) {
+            set.swap_remove(&p);
+        } else if self.common_random_numbers() {
+            // Keep the same order as the baseline's IndexSet::swap_remove
+            let infected = self.get_data_mut(InfectedPeoplePlugin);
+            if let Some(index) = infected.iter().position(|&x| x == p) {
+                infected.swap_remove(index);
+            }
         } else {
-            self.query_people((InfectionStatus, InfectionStatusValue::Infectious))
-                .len()
+            self.get_data_mut(InfectedPeoplePlugin).retain(|&x| x != p);
         }
     }
+    fn common_random_numbers(&self) -> bool {
+        self.get_data(SharedStreamPlugin).is_some()
+    }
+    fn stream(&mut self) -> &mut SharedStream<AnyRng> {
+        self.get_data_mut(SharedStreamPlugin).as_mut().unwrap()
+    }
+    fn event_times(&mut self, infection_rate: f64, recovery_rate: f64) -> (f64, f64) {
+        if self.common_random_numbers() {
+            return self.stream().event_times(infection_rate, recovery_rate);
+        }
+        // With direct sampling and nobody left to infect
+        if infection_rate == 0.0 {
+            return (
+                f64::INFINITY,
+                self.sample_distr(ModelRng, Exp::new(recovery_rate).unwrap()),
+            );
+        }
+        (
+            self.sample_distr(ModelRng, Exp::new(infection_rate).unwrap()),
+            self.sample_distr(ModelRng, Exp::new(recovery_rate).unwrap()),
+        )
+    }
     fn random_person(&mut self) -> Option<PersonId> {
-        self.sample_person(ModelRng, ())
+        if self.common_random_numbers() {
+            let population = self.get_current_population();
+            let index = self.stream().index(population);
+            return Some(self.get_data(PopulationPlugin)[index]);
+        }
+        let span = self.start_span();
+        let p = self.sample_person(ModelRng, ());
+        self.record_call("sample_person", "query", span);
+        p
     }
     fn random_infected_person(&mut self) -> Option<PersonId> {
+        // The stream indexes the infected list, so this is the same person
+        // the baseline picks even when queries are enabled.
+        if self.common_random_numbers() {
+            let n = self.infected_len();
+            if n == 0 {
+                return None;
+            }
+            let index = self.stream().index(n);
+            return Some(self.infected_at(index));
+        }
         if self.get_params().disable_queries {
-            let infected = self.get_data(InfectedPeoplePlugin);
-
-            if infected.is_empty() {
+            let n = self.infected_len();
+            if n == 0 {
                 None
             } else {
-                let index = self.sample_range(ModelRng, 0..infected.len());
-                Some(infected[index])
+                let index = self.sample_range(ModelRng, 0..n);
+                Some(self.infected_at(index))
             }
         } else {
-            self.sample_person(
+            self.get_data_mut(ModelStatsPlugin).record_query();
+            let span = self.start_span();
+            let p = self.sample_person(
                 ModelRng,
                 (InfectionStatus, InfectionStatusValue::Infectious),
-            )
+            );
+            self.record_call("sample_person", "query", span);
+            p
+        }
+    }
+    fn susceptible_people(&self) -> usize {
+        self.get_data(SusceptibleSetPlugin)
+            .as_ref()
+            .map_or(0, IndexSet::len)
+    }
+    // Like random_infected_person, from the susceptible people
+    fn random_susceptible_person(&mut self) -> Option<PersonId> {
+        let n = self.susceptible_people();
+        if n == 0 {
+            return None;
+        }
+        let index = if self.common_random_numbers() {
+            self.stream().index(n)
+        } else {
+            self.sample_range(ModelRng, 0..n)
+        };
+        self.get_data(SusceptibleSetPlugin)
+            .as_ref()
+            .map(|set| set[index])
+    }
+    // Who an infection event reaches
+    fn random_contact(&mut self) -> Option<PersonId> {
+        if self.get_params().direct_sampling {
+            self.random_susceptible_person()
+        } else {
+            self.random_person()
         }
     }
-    fn infect_person(&mut self, p: PersonId, t: Option<f64>) {
+    // Skipped unless tracking transmission. Drawn from the infected list
+    // with its own stream, so it doesn't change the epidemic either way.
+    fn sample_infector(&mut self) -> Option<PersonId> {
+        if self.get_data(TransmissionTreePlugin).is_none() {
+            return None;
+        }
+        let n = self.infected_len();
+        if n == 0 {
+            return None;
+        }
+        let index = match self.get_data_mut(InfectorStreamPlugin) {
+            Some(stream) => stream.index(n),
+            None => self.sample_range(InfectorRng, 0..n),
+        };
+        Some(self.infected_at(index))
+    }
+    fn infect_person(&mut self, p: PersonId, infector: Option<PersonId>, t: Option<f64>) {
         if self.get_person_property(p, InfectionStatus) != InfectionStatusValue::Susceptible {
             return;
         }
         let enable_stats = self.get_params().enable_stats;
         self.set_person_property(p, InfectionStatus, InfectionStatusValue::Infectious);
-        self.get_data_mut(InfectedPeoplePlugin).push(p);
+        self.add_infected(p);
+        if let Some(set) = self.get_data_mut(SusceptibleSetPlugin) {
+            set.swap_remove(&p);
+        }
 
         let stats_data = self.get_data_mut(ModelStatsPlugin);
         stats_data.record_infection();
 
+        // Only looked up for whatever needs the person's index
+        let notify = t.filter(|_| !self.get_data(ObserversPlugin).is_empty());
+        if notify.is_some() || self.get_data(TransmissionTreePlugin).is_some() {
+            let index = self.person_index(p);
+            let infector = infector.map(|infector| self.person_index(infector));
+            if let Some(tree) = self.get_data_mut(TransmissionTreePlugin) {
+                let t = t.unwrap_or(0.0);
+                match infector {
+                    Some(infector) => tree.record_infection(index, infector, t),
+                    None => tree.record_seed(index, t),
+                }
+            }
+            if let Some(t) = notify {
+                self.get_data_mut(ObserversPlugin)
+                    .infection(t, index, infector);
+            }
+        }
+
+        if let (Some(t), Some(trace)) = (t, self.get_data_mut(TracePlugin)) {
+            trace.event(t);
+        }
+
         if let Some(t) = t
             && enable_stats
         {
+            let span = self.start_span();
             self.send_report(Incidence {
                 t,
                 status: InfectionStatusValue::Infectious,
             });
+            self.record_call("send_report", "report", span);
         }
     }
     fn recover_person(&mut self, p: PersonId, t: f64) {
@@ -112,124 +350,530 @@ impl InfectionLoop for Context {
 
         let stats_data = self.get_data_mut(ModelStatsPlugin);
         stats_data.record_recovery();
-        self.get_data_mut(InfectedPeoplePlugin).retain(|&x| x != p);
+        self.remove_infected(p);
+        if !self.get_data(ObserversPlugin).is_empty() {
+            let index = self.person_index(p);
+            self.get_data_mut(ObserversPlugin).recovery(t, index);
+        }
+        if let Some(trace) = self.get_data_mut(TracePlugin) {
+            trace.event(t);
+        }
 
         if enable_stats {
+            let span = self.start_span();
             self.send_report(Incidence {
                 t,
                 status: InfectionStatusValue::Recovered,
             });
+            self.record_call("send_report", "report", span);
         }
     }
     fn next_event(&mut self) {
-        let params = self.get_params();
-        let infection_rate = params.r0 / params.infectious_period;
+        let &Parameters {
+            r0,
+            infectious_period,
+            population,
+            direct_sampling,
+            ..
+        } = self.get_params();
+        let infection_rate = r0 / infectious_period;
         let n = self.infected_people() as f64;
 
         // If there are no more infected people, exit the loop.
         if n == 0.0 {
+            self.end_epidemic();
             return;
         }
 
-        let infection_event_rate = infection_rate * n;
-        let recovery_event_rate = n / params.infectious_period;
+        let mut infection_event_rate = infection_rate * n;
+        if direct_sampling {
+            // Only contacts with susceptible people, which all infect
+            infection_event_rate *= self.susceptible_people() as f64 / population as f64;
+        }
+        let recovery_event_rate = n / infectious_period;
 
-        let infection_event_time =
-            self.sample_distr(ModelRng, Exp::new(infection_event_rate).unwrap());
-        let recovery_event_time =
-            self.sample_distr(ModelRng, Exp::new(recovery_event_rate).unwrap());
+        // Draw events until one changes someone's status. Rejected contacts
+        // don't change anything, but the time until them still passes.
+        let mut t = self.get_current_time();
+        loop {
+            let timer = self.get_stats().start_timer();
+            let (infection_event_time, recovery_event_time) =
+                self.event_times(infection_event_rate, recovery_event_rate);
 
-        let p = self.random_person().unwrap();
-        if infection_event_time < recovery_event_time {
-            if self.get_person_property(p, InfectionStatus) == InfectionStatusValue::Susceptible {
-                self.add_plan(
-                    self.get_current_time() + infection_event_time,
-                    move |context| {
-                        context.infect_person(p, Some(context.get_current_time()));
-                        if context.infected_people() > 0 {
-                            context.next_event();
+            // The shared stream only draws a contact for infection events, like the
+            // baseline, rather than before choosing the event type. So does
+            // direct sampling, which needs the susceptible people to draw one.
+            let contact = (!self.common_random_numbers() && !direct_sampling)
+                .then(|| self.random_person().unwrap());
+            if infection_event_time < recovery_event_time {
+                let p = contact.or_else(|| self.random_contact()).unwrap();
+                self.get_data_mut(ModelStatsPlugin)
+                    .record_infection_attempt();
+                if self.get_person_property(p, InfectionStatus) == InfectionStatusValue::Susceptible
+                {
+                    self.add_plan(t + infection_event_time, move |context| {
+                        let timer = context.get_stats().start_timer();
+                        let infector = context.sample_infector();
+                        let t = context.get_current_time();
+                        context.infect_person(p, infector, Some(t));
+                        let ok = context.check_invariants(t, p, CheckedEvent::Infection);
+                        context
+                            .get_data_mut(ModelStatsPlugin)
+                            .record_time(EventKind::Infection, timer);
+                        if ok {
+                            context.continue_epidemic();
                         }
-                    },
-                );
-                return;
-            }
-        } else {
-            self.add_plan(self.get_current_time() + recovery_event_time, |context| {
-                if let Some(p) = context.random_infected_person() {
-                    context.recover_person(p, context.get_current_time());
+                    });
+                    let stats = self.get_data_mut(ModelStatsPlugin);
+                    stats.record_plan();
+                    stats.record_time(EventKind::Sampling, timer);
+                    return;
                 }
-                if context.infected_people() > 0 {
-                    context.next_event();
+                t += infection_event_time;
+                let ok = self.check_invariants(t, p, CheckedEvent::RejectedContact);
+                let stats = self.get_data_mut(ModelStatsPlugin);
+                stats.record_rejected_contact();
+                stats.record_time(EventKind::Sampling, timer);
+                if !ok {
+                    return;
                 }
-            });
-            return;
+            } else {
+                self.add_plan(t + recovery_event_time, |context| {
+                    let timer = context.get_stats().start_timer();
+                    let mut ok = true;
+                    if let Some(p) = context.random_infected_person() {
+                        let t = context.get_current_time();
+                        context.recover_person(p, t);
+                        ok = context.check_invariants(t, p, CheckedEvent::Recovery);
+                    }
+                    context
+                        .get_data_mut(ModelStatsPlugin)
+                        .record_time(EventKind::Recovery, timer);
+                    if ok {
+                        context.continue_epidemic();
+                    }
+                });
+                let stats = self.get_data_mut(ModelStatsPlugin);
+                stats.record_plan();
+                stats.record_time(EventKind::Sampling, timer);
+                return;
+            }
         }
-
-        // If we didn't schedule any plans, retry.
-        self.next_event();
     }
-    fn setup(&mut self) {
+    fn start_span(&self) -> Option<Instant> {
+        self.get_data(TracePlugin).as_ref().map(Trace::start_span)
+    }
+    fn record_span(&mut self, name: &'static str, cat: &'static str, start: Option<Instant>) {
+        if let (Some(trace), Some(start)) = (self.get_data_mut(TracePlugin), start) {
+            trace.record_span(name, cat, start);
+        }
+    }
+    fn record_call(&mut self, name: &'static str, cat: &'static str, start: Option<Instant>) {
+        if let (Some(trace), Some(start)) = (self.get_data_mut(TracePlugin), start) {
+            trace.record_call(name, cat, start);
+        }
+    }
+    // Takes the kind of event so that the person's index is only looked up
+    // when checking. Shuts down at the first violation and returns whether
+    // the epidemic can go on.
+    fn check_invariants(&mut self, t: f64, p: PersonId, event: fn(usize) -> CheckedEvent) -> bool {
+        let Some(mut checker) = self.get_data_mut(InvariantPlugin).take() else {
+            return true;
+        };
+        let event = event(self.person_index(p));
+        let result = self.check_invariants_with(&mut checker, t, p, event);
+        *self.get_data_mut(InvariantPlugin) = Some(checker);
+        let Err(violation) = result else {
+            return true;
+        };
+        *self.get_data_mut(ViolationPlugin) = Some(violation);
+        *self.get_data_mut(StopReasonPlugin) = Some(StopReason::Invariant);
+        self.shutdown();
+        false
+    }
+    // Counting everyone's status would be too slow to do after every event,
+    // so this only counts the infectious people through the index
+    fn check_invariants_with(
+        &self,
+        checker: &mut InvariantChecker,
+        t: f64,
+        p: PersonId,
+        event: CheckedEvent,
+    ) -> Result<(), InvariantViolation> {
+        checker.event(t, event)?;
         let &Parameters {
             population,
             initial_infections,
+            ..
+        } = self.get_params();
+        checker.ensure(self.get_current_population() == population, || {
+            format!(
+                "{} people for a population of {population}",
+                self.get_current_population()
+            )
+        })?;
+        let infected = self.infected_len();
+        let indexed = self.query_people_count((InfectionStatus, InfectionStatusValue::Infectious));
+        checker.ensure(infected == indexed, || {
+            format!("{infected} infected people but {indexed} are infectious in the index")
+        })?;
+        checker.check_stats(self.get_stats(), initial_infections, infected, None)?;
+
+        let status = self.get_person_property(p, InfectionStatus);
+        let listed = self.is_listed_infected(p);
+        let ok = match event {
+            CheckedEvent::Infection(_) => status == InfectionStatusValue::Infectious && listed,
+            CheckedEvent::Recovery(_) => status == InfectionStatusValue::Recovered && !listed,
+            CheckedEvent::RejectedContact(_) => {
+                status != InfectionStatusValue::Susceptible
+                    && listed == (status == InfectionStatusValue::Infectious)
+            }
+        };
+        checker.ensure(ok, || {
+            let listed = if listed { "" } else { "not " };
+            format!(
+                "person {} is {status:?} and {listed}in the infected list",
+                self.person_index(p)
+            )
+        })
+    }
+    // Called after each event
+    fn continue_epidemic(&mut self) {
+        if self.infected_people() > 0 {
+            self.save_checkpoints();
+            let start = self.get_data(LoopStartPlugin).expect("set when simulating");
+            if let Some(reason) = self.get_params().stop.check(self.get_stats(), start) {
+                *self.get_data_mut(StopReasonPlugin) = Some(reason);
+                self.shutdown();
+                return;
+            }
+            self.next_event();
+        } else {
+            self.end_epidemic();
+        }
+    }
+    // Cancels the shutdown at max_time so the simulation ends at the time of
+    // the last recovery, like the baseline
+    fn end_epidemic(&mut self) {
+        *self.get_data_mut(StopReasonPlugin) = Some(StopReason::Extinction);
+        if let Some(plan) = self.get_data_mut(ShutdownPlanPlugin).take() {
+            self.cancel_plan(&plan);
+        }
+    }
+    fn save_checkpoints(&mut self) {
+        let t = self.get_current_time();
+        if !self
+            .get_data(CheckpointPlugin)
+            .as_ref()
+            .is_some_and(|checkpoints| checkpoints.due(t))
+        {
+            return;
+        }
+        // Only set with common random numbers, so this can't fail
+        let state = self.state().unwrap();
+        if let Some(checkpoints) = self.get_data_mut(CheckpointPlugin) {
+            checkpoints.save(&state);
+        }
+    }
+    // Everything but the population
+    fn init(&mut self) {
+        let &Parameters {
             seed,
-            max_time,
             enable_stats,
             disable_queries,
+            track_transmission,
+            common_random_numbers,
+            rng,
+            check_invariants,
+            trace,
+            infected_set,
+            direct_sampling,
             ..
         } = self.get_params();
 
+        // Kept when a reset carries the trace over
+        if trace && self.get_data(TracePlugin).is_none() {
+            *self.get_data_mut(TracePlugin) = Some(Trace::new());
+        }
+
         self.init_random(seed);
 
-        if !disable_queries {
+        // The invariant checks count the infectious people through the index
+        if !disable_queries || check_invariants {
             self.index_property(InfectionStatus);
         }
 
-        self.report_options().overwrite(true);
+        let prefix = self.get_data(ReportPrefixPlugin).clone();
+        self.report_options().overwrite(true).file_prefix(prefix);
 
         if enable_stats {
-            self.add_report::<Incidence>("incidence-ixa").unwrap();
+            self.add_report::<Incidence>("incidence").unwrap();
         }
 
+        if track_transmission {
+            *self.get_data_mut(TransmissionTreePlugin) = Some(TransmissionTree::new());
+        }
+
+        if common_random_numbers {
+            *self.get_data_mut(SharedStreamPlugin) = Some(SharedStream::from_rng(rng.seeded(seed)));
+            if track_transmission {
+                *self.get_data_mut(InfectorStreamPlugin) =
+                    Some(SharedStream::from_rng(rng.seeded(infector_seed(seed))));
+            }
+        }
+
+        if infected_set {
+            *self.get_data_mut(InfectedSetPlugin) = Some(IndexSet::new());
+        }
+
+        // Kept in the baseline's order, which the shared stream indexes
+        if direct_sampling {
+            *self.get_data_mut(SusceptibleSetPlugin) = Some(IndexSet::new());
+        }
+    }
+    fn setup(&mut self) {
+        let &Parameters {
+            population,
+            initial_infections,
+            max_time,
+            profile_events,
+            common_random_numbers,
+            ..
+        } = self.get_params();
+
+        self.init();
+
         // Set up population
+        let span = self.start_span();
         for _ in 0..population {
-            self.add_person(()).unwrap();
+            let p = self.add_indexed_person();
+            if common_random_numbers {
+                self.get_data_mut(PopulationPlugin).push(p);
+            }
+            if let Some(set) = self.get_data_mut(SusceptibleSetPlugin) {
+                set.insert(p);
+            }
         }
 
+        self.record_span("setup", "setup", span);
+
         // Seed infections
+        let span = self.start_span();
         let stats = self.get_data_mut(ModelStatsPlugin);
         stats.set_prevalence(initial_infections);
-        for p in self.sample_people(
-            ModelRng,
-            (InfectionStatus, InfectionStatusValue::Susceptible),
-            initial_infections,
-        ) {
+        if profile_events {
+            stats.enable_profiling();
+        }
+        let seeds = if common_random_numbers {
+            let mut seeds = Vec::new();
+            while seeds.len() < initial_infections {
+                let p = self.random_person().unwrap();
+                if !seeds.contains(&p) {
+                    seeds.push(p);
+                }
+            }
+            seeds
+        } else {
+            self.sample_people(
+                ModelRng,
+                (InfectionStatus, InfectionStatusValue::Susceptible),
+                initial_infections,
+            )
+        };
+        for p in seeds {
             self.set_person_property(p, InfectionStatus, InfectionStatusValue::Infectious);
-            self.get_data_mut(InfectedPeoplePlugin).push(p);
+            self.add_infected(p);
+            if let Some(set) = self.get_data_mut(SusceptibleSetPlugin) {
+                set.swap_remove(&p);
+            }
+            let index = self.person_index(p);
+            if let Some(tree) = self.get_data_mut(TransmissionTreePlugin) {
+                tree.record_seed(index, 0.0);
+            }
         }
 
-        self.add_plan(max_time, |context| {
+        let shutdown = self.add_plan(max_time, |context| {
             context.shutdown();
         });
+        *self.get_data_mut(ShutdownPlanPlugin) = Some(shutdown);
+        self.get_data_mut(ModelStatsPlugin).record_plan();
+
+        self.record_span("seeding", "setup", span);
+
+        assert_eq!(self.infected_people(), initial_infections);
+    }
+    fn state(&self) -> Result<ModelState, CheckpointError> {
+        // ixa's own generator can't be saved
+        let Some(stream) = self.get_data(SharedStreamPlugin) else {
+            return Err(checkpoints_unsupported());
+        };
+        Ok(ModelState {
+            params: self.get_params().clone(),
+            time: self.get_current_time(),
+            rng: serde_json::to_value(stream)?,
+            infector_rng: self
+                .get_data(InfectorStreamPlugin)
+                .as_ref()
+                .map(serde_json::to_value)
+                .transpose()?,
+            statuses: self
+                .get_data(PopulationPlugin)
+                .iter()
+                .map(|&p| match self.get_person_property(p, InfectionStatus) {
+                    InfectionStatusValue::Susceptible => Status::Susceptible,
+                    InfectionStatusValue::Infectious => Status::Infectious,
+                    InfectionStatusValue::Recovered => Status::Recovered,
+                })
+                .collect(),
+            infectious: (0..self.infected_len())
+                .map(|i| self.person_index(self.infected_at(i)))
+                .collect(),
+            susceptible: match self.get_data(SusceptibleSetPlugin) {
+                Some(set) => set.iter().map(|&p| self.person_index(p)).collect(),
+                None => Vec::new(),
+            },
+            stats: self.get_stats().clone(),
+            transmission_tree: self.get_data(TransmissionTreePlugin).clone(),
+        })
+    }
+    fn restore(&mut self, state: ModelState) -> Result<(), CheckpointError> {
+        let susceptible = state.susceptible_order();
+        self.init();
+        *self.get_data_mut(SharedStreamPlugin) = Some(serde_json::from_value(state.rng)?);
+        if let Some(rng) = state.infector_rng {
+            *self.get_data_mut(InfectorStreamPlugin) = Some(serde_json::from_value(rng)?);
+        }
+        *self.get_data_mut(TransmissionTreePlugin) = state.transmission_tree;
+        *self.get_data_mut(ModelStatsPlugin) = state.stats;
+
+        for status in state.statuses {
+            let p = self.add_indexed_person();
+            self.get_data_mut(PopulationPlugin).push(p);
+            let value = match status {
+                Status::Susceptible => continue,
+                Status::Infectious => InfectionStatusValue::Infectious,
+                Status::Recovered => InfectionStatusValue::Recovered,
+            };
+            self.set_person_property(p, InfectionStatus, value);
+        }
+        for i in state.infectious {
+            let p = self.get_data(PopulationPlugin)[i];
+            self.add_infected(p);
+        }
+        if self.get_data(SusceptibleSetPlugin).is_some() {
+            let people: IndexSet<PersonId> = susceptible
+                .into_iter()
+                .map(|i| self.get_data(PopulationPlugin)[i])
+                .collect();
+            *self.get_data_mut(SusceptibleSetPlugin) = Some(people);
+        }
 
-        assert_eq!(self.infected_people(), 5);
+        // Draw the next event at the checkpoint's time, as the run it came
+        // from did after saving it
+        self.add_plan(state.time, |context| context.next_event());
+        let max_time = self.get_params().max_time;
+        let shutdown = self.add_plan(max_time, |context| {
+            context.shutdown();
+        });
+        *self.get_data_mut(ShutdownPlanPlugin) = Some(shutdown);
+        Ok(())
     }
 }
 
+fn checkpoints_unsupported() -> CheckpointError {
+    CheckpointError::Unsupported(
+        "ixa can only save its state with common random numbers".to_string(),
+    )
+}
+
 impl Model {
     pub fn new(params: Parameters) -> Self {
         let mut ctx = Context::new();
         ctx.set_global_property_value(Params, params).unwrap();
-        Self { ctx }
+        Self {
+            ctx,
+            resumed: false,
+        }
+    }
+    // Continues from a checkpoint instead of setting up
+    pub fn restore(state: ModelState) -> Result<Self, CheckpointError> {
+        state.validate()?;
+        if !state.params.common_random_numbers {
+            return Err(checkpoints_unsupported());
+        }
+        let mut model = Self::new(state.params.clone());
+        model.ctx.restore(state)?;
+        model.resumed = true;
+        Ok(model)
+    }
+    // Returns to the state just after setup with a new seed. ixa can't
+    // execute a Context twice, so this sets up a new one and none of the last
+    // run's storage is reused; only the observers, any checkpoints that
+    // haven't been reached and the report prefix carry over. Like the
+    // baseline, the trace starts again with the new run.
+    pub fn reset(&mut self, seed: u64) -> Result<(), ParameterError> {
+        let params = Parameters {
+            seed,
+            ..self.ctx.get_params().clone()
+        };
+        let mut observers = std::mem::take(self.ctx.get_data_mut(ObserversPlugin));
+        observers.reset();
+        let checkpoints = self.ctx.get_data_mut(CheckpointPlugin).take();
+        let prefix = std::mem::take(self.ctx.get_data_mut(ReportPrefixPlugin));
+        *self = Self::new(params);
+        *self.ctx.get_data_mut(ReportPrefixPlugin) = prefix;
+        *self.ctx.get_data_mut(ObserversPlugin) = observers;
+        *self.ctx.get_data_mut(CheckpointPlugin) = checkpoints;
+        self.setup()
     }
     pub fn get_stats(&self) -> &ModelStats {
         self.ctx.get_stats()
     }
-    pub fn run(&mut self) {
+    pub fn get_transmission_tree(&self) -> Option<&TransmissionTree> {
+        self.ctx.get_data(TransmissionTreePlugin).as_ref()
+    }
+    pub fn get_trace(&self) -> Option<&Trace> {
+        self.ctx.get_data(TracePlugin).as_ref()
+    }
+    pub fn run(&mut self) -> Result<(), ParameterError> {
+        self.setup()?;
+        self.simulate();
+        Ok(())
+    }
+    pub fn setup(&mut self) -> Result<(), ParameterError> {
+        self.ctx.get_params().validate()?;
         self.ctx.setup();
+        Ok(())
+    }
+    pub fn simulate(&mut self) {
+        *self.ctx.get_data_mut(LoopStartPlugin) = Some(Instant::now());
+        *self.ctx.get_data_mut(StopReasonPlugin) = None;
+        *self.ctx.get_data_mut(ViolationPlugin) = None;
+        let checker = self
+            .ctx
+            .get_params()
+            .check_invariants
+            .then(|| InvariantChecker::new(self.ctx.get_current_time()));
+        *self.ctx.get_data_mut(InvariantPlugin) = checker;
+        let span = self.ctx.start_span();
         // Set up the first event in the loop
-        self.ctx.next_event();
+        if !self.resumed {
+            self.ctx.next_event();
+        }
         self.ctx.execute();
+        // Otherwise the shutdown plan ended it
+        self.ctx
+            .get_data_mut(StopReasonPlugin)
+            .get_or_insert(StopReason::MaxTime);
+        let t = self.ctx.get_current_time();
+        if let Some(trace) = self.ctx.get_data_mut(TracePlugin) {
+            trace.finish(t);
+        }
+        self.ctx.record_span("simulate", "simulate", span);
+
+        let mut observers = std::mem::take(self.ctx.get_data_mut(ObserversPlugin));
+        observers.finish(self.ctx.get_current_time(), self.get_stats());
+        *self.ctx.get_data_mut(ObserversPlugin) = observers;
     }
 }
 
@@ -240,8 +884,11 @@ impl SIRModel for Model {
     fn current_time(&self) -> f64 {
         self.ctx.get_current_time()
     }
-    fn run(&mut self) {
-        self.run();
+    fn setup(&mut self) -> Result<(), ParameterError> {
+        self.setup()
+    }
+    fn simulate(&mut self) {
+        self.simulate();
     }
     fn get_stats(&self) -> &ModelStats {
         self.get_stats()
@@ -249,6 +896,37 @@ impl SIRModel for Model {
     fn get_params(&self) -> &Parameters {
         self.ctx.get_params()
     }
+    fn get_transmission_tree(&self) -> Option<&TransmissionTree> {
+        self.get_transmission_tree()
+    }
+    fn reset(&mut self, seed: u64) -> Result<(), ParameterError> {
+        self.reset(seed)
+    }
+    fn get_trace(&self) -> Option<&Trace> {
+        self.get_trace()
+    }
+    fn stop_reason(&self) -> Option<StopReason> {
+        *self.ctx.get_data(StopReasonPlugin)
+    }
+    fn invariant_violation(&self) -> Option<&InvariantViolation> {
+        self.ctx.get_data(ViolationPlugin).as_ref()
+    }
+    fn add_observer(&mut self, observer: Box<dyn SimulationObserver>) {
+        self.ctx.get_data_mut(ObserversPlugin).add(observer);
+    }
+    fn set_checkpoints(&mut self, checkpoints: Checkpoints) -> Result<(), CheckpointError> {
+        if !self.ctx.get_params().common_random_numbers {
+            return Err(checkpoints_unsupported());
+        }
+        *self.ctx.get_data_mut(CheckpointPlugin) = Some(checkpoints);
+        Ok(())
+    }
+    fn checkpoint(&self) -> Result<ModelState, CheckpointError> {
+        self.ctx.state()
+    }
+    fn set_report_prefix(&mut self, prefix: &str) {
+        *self.ctx.get_data_mut(ReportPrefixPlugin) = prefix.to_string();
+    }
 }
 
 #[cfg(test)]
@@ -264,7 +942,12 @@ mod test {
             disable_queries: true,
             ..Parameters::default()
         });
-        for model in [&mut m1, &mut m2] {
+        let mut m3 = Model::new(Parameters {
+            disable_queries: true,
+            infected_set: true,
+            ..Parameters::default()
+        });
+        for model in [&mut m1, &mut m2, &mut m3] {
             model.ctx.setup();
             assert_eq!(model.ctx.infected_people(), 5);
             let p = model
@@ -274,7 +957,7 @@ mod test {
                     (InfectionStatus, InfectionStatusValue::Susceptible),
                 )
                 .unwrap();
-            model.ctx.infect_person(p, Some(0.0));
+            model.ctx.infect_person(p, None, Some(0.0));
             assert_eq!(model.ctx.infected_people(), 6);
             assert_eq!(model.ctx.get_stats().get_cum_incidence(), 1);
             model.ctx.recover_person(p, 0.0);
@@ -283,6 +966,22 @@ mod test {
         }
     }
 
+    #[test]
+    fn setup_validates_params() {
+        let mut model = Model::new(Parameters {
+            initial_infections: 12,
+            ..Parameters::default()
+        });
+        model.setup().unwrap();
+        assert_eq!(model.ctx.infected_people(), 12);
+
+        let mut model = Model::new(Parameters {
+            r0: -1.0,
+            ..Parameters::default()
+        });
+        assert_eq!(model.run(), Err(ParameterError::InvalidR0(-1.0)));
+    }
+
     #[test]
     fn get_random_infected_person() {
         let mut model = Model::new(Parameters {
@@ -300,7 +999,7 @@ mod test {
         let population = 100_000;
         params.population = population;
         let mut model = Model::new(params);
-        model.run();
+        model.run().unwrap();
 
         // Final size relation is ~58%
         let incidence = model.get_stats().get_cum_incidence() as f64;
@@ -315,7 +1014,7 @@ mod test {
         let params = Parameters::default();
 
         let mut model1 = Model::new(params.clone());
-        model1.run();
+        model1.run().unwrap();
 
         let mut model2 = Model::new(Parameters {
             disable_queries: true,
@@ -328,7 +1027,7 @@ mod test {
                 .query_people((InfectionStatus, InfectionStatusValue::Infectious))
                 .len(),
         );
-        model2.run();
+        model2.run().unwrap();
 
         assert_eq!(
             model1.get_stats().get_cum_incidence(),
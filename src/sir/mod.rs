@@ -1,2 +1,3 @@
 pub mod baseline;
 pub mod ixa;
+pub mod store;
@@ -0,0 +1,503 @@
+use clap::ValueEnum;
+use indexmap::IndexSet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InfectionStatus {
+    Susceptible,
+    Infectious,
+    Recovered,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PersonId {
+    pub(super) id: usize,
+}
+
+// How the baseline keeps its population. `ModelKind::into_model` picks the
+// implementation from `Parameters::store`, like the generator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StoreKind {
+    // An IndexSet per status and a Vec of statuses
+    #[default]
+    IndexSet,
+    // A Vec per status, with each person's position in theirs
+    VecPositions,
+    // A bitset per status; sampling scans for the nth set bit
+    Bitset,
+    // u8 statuses and u32 ids and positions in separate arrays
+    CompactSoa,
+}
+
+impl StoreKind {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::IndexSet,
+            Self::VecPositions,
+            Self::Bitset,
+            Self::CompactSoa,
+        ]
+    }
+    // The name used on the command line, e.g. "vec-positions"
+    pub fn name(self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+}
+
+// The people of the baseline and their statuses. People only move from
+// susceptible to infectious to recovered, and the model only samples
+// susceptible and infectious people, so stores don't need to be able to
+// list the recovered ones.
+//
+// `nth` orders people the way removing them with `IndexSet::swap_remove`
+// would, except in `BitsetStore`, which orders them by id; so every store but
+// that one gives the same epidemic for the same seed.
+pub trait PopulationStore: Default + 'static {
+    // Removes everyone, keeping the storage
+    fn clear(&mut self);
+    fn add_person(&mut self, status: InfectionStatus) -> PersonId;
+    fn population(&self) -> usize;
+    fn status(&self, person: PersonId) -> InfectionStatus;
+    fn set_status(&mut self, person: PersonId, status: InfectionStatus);
+    fn count(&self, status: InfectionStatus) -> usize;
+    // The `index`th susceptible or infectious person
+    fn nth(&self, status: InfectionStatus, index: usize) -> PersonId;
+    // Whether the store's own record of the people with `status` has
+    // `person`, for the invariant checks
+    fn contains(&self, status: InfectionStatus, person: PersonId) -> bool;
+}
+
+#[derive(Default)]
+pub struct IndexSetStore {
+    pub(super) statuses: Vec<InfectionStatus>,
+    susceptible: IndexSet<PersonId>,
+    infectious: IndexSet<PersonId>,
+    recovered: IndexSet<PersonId>,
+}
+
+impl IndexSetStore {
+    fn set(&mut self, status: InfectionStatus) -> &mut IndexSet<PersonId> {
+        match status {
+            InfectionStatus::Susceptible => &mut self.susceptible,
+            InfectionStatus::Infectious => &mut self.infectious,
+            InfectionStatus::Recovered => &mut self.recovered,
+        }
+    }
+}
+
+impl PopulationStore for IndexSetStore {
+    fn clear(&mut self) {
+        self.statuses.clear();
+        self.susceptible.clear();
+        self.infectious.clear();
+        self.recovered.clear();
+    }
+    fn add_person(&mut self, status: InfectionStatus) -> PersonId {
+        let person = PersonId {
+            id: self.statuses.len(),
+        };
+        self.statuses.push(status);
+        self.set(status).insert(person);
+        person
+    }
+    fn population(&self) -> usize {
+        self.statuses.len()
+    }
+    fn status(&self, person: PersonId) -> InfectionStatus {
+        self.statuses[person.id]
+    }
+    fn set_status(&mut self, person: PersonId, status: InfectionStatus) {
+        let old = self.statuses[person.id];
+        self.set(old).swap_remove(&person);
+        self.set(status).insert(person);
+        self.statuses[person.id] = status;
+    }
+    fn count(&self, status: InfectionStatus) -> usize {
+        match status {
+            InfectionStatus::Susceptible => self.susceptible.len(),
+            InfectionStatus::Infectious => self.infectious.len(),
+            InfectionStatus::Recovered => self.recovered.len(),
+        }
+    }
+    fn nth(&self, status: InfectionStatus, index: usize) -> PersonId {
+        match status {
+            InfectionStatus::Susceptible => self.susceptible[index],
+            InfectionStatus::Infectious => self.infectious[index],
+            InfectionStatus::Recovered => self.recovered[index],
+        }
+    }
+    fn contains(&self, status: InfectionStatus, person: PersonId) -> bool {
+        match status {
+            InfectionStatus::Susceptible => self.susceptible.contains(&person),
+            InfectionStatus::Infectious => self.infectious.contains(&person),
+            InfectionStatus::Recovered => self.recovered.contains(&person),
+        }
+    }
+}
+
+fn status_index(status: InfectionStatus) -> usize {
+    match status {
+        InfectionStatus::Susceptible => 0,
+        InfectionStatus::Infectious => 1,
+        InfectionStatus::Recovered => 2,
+    }
+}
+
+#[derive(Default)]
+pub struct VecPositionsStore {
+    statuses: Vec<InfectionStatus>,
+    // Each person's index in the list of their status
+    positions: Vec<usize>,
+    lists: [Vec<PersonId>; 3],
+}
+
+impl PopulationStore for VecPositionsStore {
+    fn clear(&mut self) {
+        self.statuses.clear();
+        self.positions.clear();
+        for list in &mut self.lists {
+            list.clear();
+        }
+    }
+    fn add_person(&mut self, status: InfectionStatus) -> PersonId {
+        let person = PersonId {
+            id: self.statuses.len(),
+        };
+        let list = &mut self.lists[status_index(status)];
+        self.statuses.push(status);
+        self.positions.push(list.len());
+        list.push(person);
+        person
+    }
+    fn population(&self) -> usize {
+        self.statuses.len()
+    }
+    fn status(&self, person: PersonId) -> InfectionStatus {
+        self.statuses[person.id]
+    }
+    fn set_status(&mut self, person: PersonId, status: InfectionStatus) {
+        let old = &mut self.lists[status_index(self.statuses[person.id])];
+        let position = self.positions[person.id];
+        old.swap_remove(position);
+        if let Some(&moved) = old.get(position) {
+            self.positions[moved.id] = position;
+        }
+        let new = &mut self.lists[status_index(status)];
+        self.positions[person.id] = new.len();
+        new.push(person);
+        self.statuses[person.id] = status;
+    }
+    fn count(&self, status: InfectionStatus) -> usize {
+        self.lists[status_index(status)].len()
+    }
+    fn nth(&self, status: InfectionStatus, index: usize) -> PersonId {
+        self.lists[status_index(status)][index]
+    }
+    fn contains(&self, status: InfectionStatus, person: PersonId) -> bool {
+        self.lists[status_index(status)].get(self.positions[person.id]) == Some(&person)
+    }
+}
+
+#[derive(Default)]
+pub struct BitsetStore {
+    population: usize,
+    bits: [Vec<u64>; 3],
+    counts: [usize; 3],
+}
+
+impl BitsetStore {
+    fn has(&self, status: InfectionStatus, id: usize) -> bool {
+        self.bits[status_index(status)][id / 64] & (1 << (id % 64)) != 0
+    }
+    fn flip(&mut self, status: InfectionStatus, id: usize) {
+        self.bits[status_index(status)][id / 64] ^= 1 << (id % 64);
+    }
+}
+
+impl PopulationStore for BitsetStore {
+    fn clear(&mut self) {
+        self.population = 0;
+        for bits in &mut self.bits {
+            bits.clear();
+        }
+        self.counts = [0; 3];
+    }
+    fn add_person(&mut self, status: InfectionStatus) -> PersonId {
+        let person = PersonId {
+            id: self.population,
+        };
+        if person.id.is_multiple_of(64) {
+            for bits in &mut self.bits {
+                bits.push(0);
+            }
+        }
+        self.population += 1;
+        self.flip(status, person.id);
+        self.counts[status_index(status)] += 1;
+        person
+    }
+    fn population(&self) -> usize {
+        self.population
+    }
+    fn status(&self, person: PersonId) -> InfectionStatus {
+        if self.has(InfectionStatus::Infectious, person.id) {
+            InfectionStatus::Infectious
+        } else if self.has(InfectionStatus::Susceptible, person.id) {
+            InfectionStatus::Susceptible
+        } else {
+            InfectionStatus::Recovered
+        }
+    }
+    fn set_status(&mut self, person: PersonId, status: InfectionStatus) {
+        let old = self.status(person);
+        self.flip(old, person.id);
+        self.counts[status_index(old)] -= 1;
+        self.flip(status, person.id);
+        self.counts[status_index(status)] += 1;
+    }
+    fn count(&self, status: InfectionStatus) -> usize {
+        self.counts[status_index(status)]
+    }
+    fn nth(&self, status: InfectionStatus, index: usize) -> PersonId {
+        // Of the people with `status` in the words still to look at
+        let mut rest = index;
+        for (i, &word) in self.bits[status_index(status)].iter().enumerate() {
+            let ones = word.count_ones() as usize;
+            if rest < ones {
+                let mut word = word;
+                for _ in 0..rest {
+                    word &= word - 1;
+                }
+                return PersonId {
+                    id: i * 64 + word.trailing_zeros() as usize,
+                };
+            }
+            rest -= ones;
+        }
+        let count = self.counts[status_index(status)];
+        panic!("no person {index} among the {count} {status:?} people");
+    }
+    fn contains(&self, status: InfectionStatus, person: PersonId) -> bool {
+        self.has(status, person.id)
+    }
+}
+
+const SUSCEPTIBLE: u8 = 0;
+const INFECTIOUS: u8 = 1;
+const RECOVERED: u8 = 2;
+
+// Like `VecPositionsStore` with a byte per status and 32-bit ids. Nobody
+// samples the recovered people, so they are only counted.
+#[derive(Default)]
+pub struct CompactSoaStore {
+    statuses: Vec<u8>,
+    positions: Vec<u32>,
+    susceptible: Vec<u32>,
+    infectious: Vec<u32>,
+    recovered: usize,
+}
+
+impl CompactSoaStore {
+    fn list(&mut self, status: u8) -> Option<&mut Vec<u32>> {
+        match status {
+            SUSCEPTIBLE => Some(&mut self.susceptible),
+            INFECTIOUS => Some(&mut self.infectious),
+            _ => None,
+        }
+    }
+}
+
+fn to_u8(status: InfectionStatus) -> u8 {
+    match status {
+        InfectionStatus::Susceptible => SUSCEPTIBLE,
+        InfectionStatus::Infectious => INFECTIOUS,
+        InfectionStatus::Recovered => RECOVERED,
+    }
+}
+
+impl PopulationStore for CompactSoaStore {
+    fn clear(&mut self) {
+        self.statuses.clear();
+        self.positions.clear();
+        self.susceptible.clear();
+        self.infectious.clear();
+        self.recovered = 0;
+    }
+    fn add_person(&mut self, status: InfectionStatus) -> PersonId {
+        let id = self.statuses.len();
+        let id32 = u32::try_from(id).expect("at most u32::MAX people");
+        let status = to_u8(status);
+        self.statuses.push(status);
+        match self.list(status) {
+            Some(list) => {
+                let position = list.len() as u32;
+                list.push(id32);
+                self.positions.push(position);
+            }
+            None => {
+                self.recovered += 1;
+                self.positions.push(0);
+            }
+        }
+        PersonId { id }
+    }
+    fn population(&self) -> usize {
+        self.statuses.len()
+    }
+    fn status(&self, person: PersonId) -> InfectionStatus {
+        match self.statuses[person.id] {
+            SUSCEPTIBLE => InfectionStatus::Susceptible,
+            INFECTIOUS => InfectionStatus::Infectious,
+            _ => InfectionStatus::Recovered,
+        }
+    }
+    fn set_status(&mut self, person: PersonId, status: InfectionStatus) {
+        let position = self.positions[person.id] as usize;
+        let old = self.statuses[person.id];
+        match self.list(old) {
+            Some(list) => {
+                list.swap_remove(position);
+                if let Some(&moved) = list.get(position) {
+                    self.positions[moved as usize] = position as u32;
+                }
+            }
+            None => self.recovered -= 1,
+        }
+        let status = to_u8(status);
+        self.statuses[person.id] = status;
+        match self.list(status) {
+            Some(list) => {
+                let position = list.len() as u32;
+                list.push(person.id as u32);
+                self.positions[person.id] = position;
+            }
+            None => self.recovered += 1,
+        }
+    }
+    fn count(&self, status: InfectionStatus) -> usize {
+        match status {
+            InfectionStatus::Susceptible => self.susceptible.len(),
+            InfectionStatus::Infectious => self.infectious.len(),
+            InfectionStatus::Recovered => self.recovered,
+        }
+    }
+    fn nth(&self, status: InfectionStatus, index: usize) -> PersonId {
+        let id = match status {
+            InfectionStatus::Susceptible => self.susceptible[index],
+            InfectionStatus::Infectious => self.infectious[index],
+            InfectionStatus::Recovered => panic!("recovered people are only counted"),
+        };
+        PersonId { id: id as usize }
+    }
+    fn contains(&self, status: InfectionStatus, person: PersonId) -> bool {
+        let position = self.positions[person.id] as usize;
+        let id = person.id as u32;
+        match status {
+            InfectionStatus::Susceptible => self.susceptible.get(position) == Some(&id),
+            InfectionStatus::Infectious => self.infectious.get(position) == Some(&id),
+            InfectionStatus::Recovered => self.statuses[person.id] == RECOVERED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters, fingerprint::Fingerprint};
+    use std::{cell::RefCell, rc::Rc};
+
+    // Makes the same changes to a store, checking it along the way
+    fn exercise<S: PopulationStore>() -> Vec<PersonId> {
+        use InfectionStatus::*;
+        let mut store = S::default();
+        for _ in 0..2 {
+            store.clear();
+            for _ in 0..130 {
+                store.add_person(Susceptible);
+            }
+            store.add_person(Recovered);
+        }
+        for id in [3, 70, 129, 64, 5] {
+            store.set_status(PersonId { id }, Infectious);
+        }
+        store.set_status(PersonId { id: 70 }, Recovered);
+        store.set_status(PersonId { id: 3 }, Recovered);
+
+        assert_eq!(store.population(), 131);
+        assert_eq!(
+            [Susceptible, Infectious, Recovered].map(|s| store.count(s)),
+            [125, 3, 3]
+        );
+        assert_eq!(store.status(PersonId { id: 70 }), Recovered);
+        assert_eq!(store.status(PersonId { id: 64 }), Infectious);
+        assert_eq!(store.status(PersonId { id: 0 }), Susceptible);
+        for id in 0..131 {
+            let person = PersonId { id };
+            let status = store.status(person);
+            for other in [Susceptible, Infectious, Recovered] {
+                assert_eq!(store.contains(other, person), other == status);
+            }
+        }
+        let infectious: Vec<_> = (0..3).map(|i| store.nth(Infectious, i)).collect();
+        let mut susceptible: Vec<_> = (0..125).map(|i| store.nth(Susceptible, i).id).collect();
+        susceptible.sort();
+        susceptible.dedup();
+        assert_eq!(susceptible.len(), 125);
+        infectious
+    }
+
+    #[test]
+    fn stores_keep_the_statuses() {
+        let ids = |people: Vec<PersonId>| people.iter().map(|p| p.id).collect::<Vec<_>>();
+        let swap_removed = ids(exercise::<IndexSetStore>());
+        assert_eq!(swap_removed, vec![64, 5, 129]);
+        assert_eq!(ids(exercise::<VecPositionsStore>()), swap_removed);
+        assert_eq!(ids(exercise::<CompactSoaStore>()), swap_removed);
+        assert_eq!(ids(exercise::<BitsetStore>()), vec![5, 64, 129]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no person 3 among the 2 Infectious people")]
+    fn bitset_names_the_missing_person() {
+        let mut store = BitsetStore::default();
+        for _ in 0..100 {
+            store.add_person(InfectionStatus::Susceptible);
+        }
+        store.set_status(PersonId { id: 10 }, InfectionStatus::Infectious);
+        store.set_status(PersonId { id: 90 }, InfectionStatus::Infectious);
+        store.nth(InfectionStatus::Infectious, 3);
+    }
+
+    fn fingerprint(store: StoreKind, common_random_numbers: bool) -> String {
+        let fingerprint = Rc::new(RefCell::new(Fingerprint::default()));
+        let mut model = ModelKind::Baseline.into_model(Parameters {
+            population: 5000,
+            check_invariants: true,
+            track_transmission: true,
+            common_random_numbers,
+            store,
+            ..Parameters::default()
+        });
+        model.add_observer(Box::new(fingerprint.clone()));
+        model.run().unwrap();
+        model.reset(1).unwrap();
+        model.simulate();
+        fingerprint.borrow().hash()
+    }
+
+    #[test]
+    fn stores_give_the_same_epidemics() {
+        for common_random_numbers in [false, true] {
+            let expected = fingerprint(StoreKind::IndexSet, common_random_numbers);
+            for store in [StoreKind::VecPositions, StoreKind::CompactSoa] {
+                assert_eq!(fingerprint(store, common_random_numbers), expected);
+            }
+            // Samples in another order, but keeps its invariants
+            assert_ne!(
+                fingerprint(StoreKind::Bitset, common_random_numbers),
+                expected
+            );
+        }
+    }
+}
@@ -1,6 +1,39 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct EventCounts {
+    // Infection events drawn in the loop, whether or not the contact was susceptible
+    pub infection_attempts: usize,
+    pub infections: usize,
+    pub rejected_contacts: usize,
+    pub recoveries: usize,
+    pub plans_scheduled: usize,
+    pub queries: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum EventKind {
+    // Drawing event times and contacts, including rejected contacts
+    Sampling,
+    Infection,
+    Recovery,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct EventTimes {
+    pub sampling_seconds: f64,
+    pub infection_seconds: f64,
+    pub recovery_seconds: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ModelStats {
     cum_incidence: usize,
     prevalence: usize,
+    peak_prevalence: usize,
+    events: EventCounts,
+    event_times: Option<EventTimes>,
 }
 
 impl ModelStats {
@@ -8,17 +41,54 @@ impl ModelStats {
         Self {
             cum_incidence: 0,
             prevalence: initial_infections,
+            peak_prevalence: initial_infections,
+            events: EventCounts::default(),
+            event_times: None,
         }
     }
+    pub fn enable_profiling(&mut self) {
+        self.event_times = Some(EventTimes::default());
+    }
     pub fn set_prevalence(&mut self, value: usize) {
         self.prevalence = value;
+        self.peak_prevalence = self.peak_prevalence.max(value);
     }
     pub fn record_recovery(&mut self) {
         self.prevalence -= 1;
+        self.events.recoveries += 1;
     }
     pub fn record_infection(&mut self) {
         self.cum_incidence += 1;
         self.prevalence += 1;
+        self.peak_prevalence = self.peak_prevalence.max(self.prevalence);
+        self.events.infections += 1;
+    }
+    pub fn record_infection_attempt(&mut self) {
+        self.events.infection_attempts += 1;
+    }
+    pub fn record_rejected_contact(&mut self) {
+        self.events.rejected_contacts += 1;
+    }
+    pub fn record_plan(&mut self) {
+        self.events.plans_scheduled += 1;
+    }
+    pub fn record_query(&mut self) {
+        self.events.queries += 1;
+    }
+    // Returns None unless profiling so that timing costs nothing by default
+    pub fn start_timer(&self) -> Option<Instant> {
+        self.event_times.map(|_| Instant::now())
+    }
+    pub fn record_time(&mut self, kind: EventKind, timer: Option<Instant>) {
+        let (Some(times), Some(start)) = (&mut self.event_times, timer) else {
+            return;
+        };
+        let elapsed = start.elapsed().as_secs_f64();
+        match kind {
+            EventKind::Sampling => times.sampling_seconds += elapsed,
+            EventKind::Infection => times.infection_seconds += elapsed,
+            EventKind::Recovery => times.recovery_seconds += elapsed,
+        }
     }
     pub fn get_cum_incidence(&self) -> usize {
         self.cum_incidence
@@ -26,6 +96,15 @@ impl ModelStats {
     pub fn get_prevalence(&self) -> usize {
         self.prevalence
     }
+    pub fn get_peak_prevalence(&self) -> usize {
+        self.peak_prevalence
+    }
+    pub fn get_events(&self) -> &EventCounts {
+        &self.events
+    }
+    pub fn get_event_times(&self) -> Option<&EventTimes> {
+        self.event_times.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -45,5 +124,25 @@ mod tests {
         stats.record_recovery();
         assert_eq!(stats.get_cum_incidence(), 1);
         assert_eq!(stats.get_prevalence(), 5);
+        assert_eq!(stats.get_peak_prevalence(), 6);
+        assert_eq!(stats.get_events().infections, 1);
+        assert_eq!(stats.get_events().recoveries, 1);
+    }
+
+    #[test]
+    fn event_times() {
+        let mut stats = ModelStats::new(5);
+        let timer = stats.start_timer();
+        assert!(timer.is_none());
+        stats.record_time(EventKind::Infection, timer);
+        assert!(stats.get_event_times().is_none());
+
+        stats.enable_profiling();
+        let timer = stats.start_timer();
+        assert!(timer.is_some());
+        stats.record_time(EventKind::Recovery, timer);
+        let times = stats.get_event_times().unwrap();
+        assert!(times.recovery_seconds > 0.0);
+        assert_eq!(times.infection_seconds, 0.0);
     }
 }
@@ -0,0 +1,182 @@
+use crate::{base::ParameterError, stats::ModelStats};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Instant};
+
+// Reading the clock is slow next to a baseline event, so only check the
+// budget this often
+const EVENTS_PER_CLOCK_CHECK: usize = 256;
+
+// Optional limits on a run besides `max_time`, checked after every infection
+// and recovery
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct StopConditions {
+    // Wall time of the epidemic loop, not counting setup
+    pub max_wall_seconds: Option<f64>,
+    // Infection attempts (including rejected contacts) and recoveries
+    pub max_events: Option<usize>,
+    // Stop once cumulative incidence reaches this
+    pub max_incidence: Option<usize>,
+    // Stop once prevalence falls below this, after having reached it
+    pub min_prevalence: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    // Nobody is infectious
+    Extinction,
+    MaxTime,
+    WallClock,
+    MaxEvents,
+    Incidence,
+    Prevalence,
+    // `check_invariants` found an inconsistency, which the model keeps
+    Invariant,
+}
+
+impl StopReason {
+    // Whether the run was cut short by one of the `StopConditions`
+    pub fn is_limit(self) -> bool {
+        !matches!(
+            self,
+            StopReason::Extinction | StopReason::MaxTime | StopReason::Invariant
+        )
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StopReason::Extinction => "extinction",
+            StopReason::MaxTime => "max-time",
+            StopReason::WallClock => "wall-clock",
+            StopReason::MaxEvents => "max-events",
+            StopReason::Incidence => "incidence",
+            StopReason::Prevalence => "prevalence",
+            StopReason::Invariant => "invariant",
+        };
+        f.write_str(name)
+    }
+}
+
+impl StopConditions {
+    pub fn validate(&self) -> Result<(), ParameterError> {
+        if let Some(seconds) = self.max_wall_seconds
+            && !(seconds.is_finite() && seconds >= 0.0)
+        {
+            return Err(ParameterError::InvalidWallClockBudget(seconds));
+        }
+        Ok(())
+    }
+
+    // Which condition, if any, ends a run whose epidemic loop started at
+    // `start` and has reached `stats`. Events are counted from the stats, so
+    // a resumed run continues counting where it left off.
+    pub fn check(&self, stats: &ModelStats, start: Instant) -> Option<StopReason> {
+        if let Some(max_incidence) = self.max_incidence
+            && stats.get_cum_incidence() >= max_incidence
+        {
+            return Some(StopReason::Incidence);
+        }
+        if let Some(min_prevalence) = self.min_prevalence
+            && stats.get_prevalence() < min_prevalence
+            && stats.get_peak_prevalence() >= min_prevalence
+        {
+            return Some(StopReason::Prevalence);
+        }
+        if self.max_events.is_none() && self.max_wall_seconds.is_none() {
+            return None;
+        }
+        let counts = stats.get_events();
+        let events = counts.infection_attempts + counts.recoveries;
+        if self
+            .max_events
+            .is_some_and(|max_events| events >= max_events)
+        {
+            return Some(StopReason::MaxEvents);
+        }
+        // Only called after events that change someone's status, so count those
+        if let Some(seconds) = self.max_wall_seconds
+            && (stats.get_cum_incidence() + counts.recoveries)
+                .is_multiple_of(EVENTS_PER_CLOCK_CHECK)
+            && start.elapsed().as_secs_f64() >= seconds
+        {
+            return Some(StopReason::WallClock);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters};
+
+    fn run(kind: ModelKind, stop: StopConditions) -> (StopReason, f64, usize) {
+        let mut model = kind.into_model(Parameters {
+            population: 5000,
+            max_time: 1000.0,
+            common_random_numbers: true,
+            stop,
+            ..Parameters::default()
+        });
+        model.run().unwrap();
+        (
+            model.stop_reason().unwrap(),
+            model.current_time(),
+            model.get_stats().get_cum_incidence(),
+        )
+    }
+
+    #[test]
+    fn models_stop_at_the_same_event() {
+        let conditions = [
+            (StopConditions::default(), StopReason::Extinction),
+            (
+                StopConditions {
+                    max_events: Some(1000),
+                    ..StopConditions::default()
+                },
+                StopReason::MaxEvents,
+            ),
+            (
+                StopConditions {
+                    max_incidence: Some(500),
+                    ..StopConditions::default()
+                },
+                StopReason::Incidence,
+            ),
+            (
+                StopConditions {
+                    min_prevalence: Some(50),
+                    ..StopConditions::default()
+                },
+                StopReason::Prevalence,
+            ),
+        ];
+        for (stop, reason) in conditions {
+            let runs: Vec<_> = ModelKind::all()
+                .into_iter()
+                .map(|kind| run(kind, stop))
+                .collect();
+            assert_eq!(runs[0].0, reason);
+            for run in &runs[1..] {
+                assert_eq!(&runs[0], run);
+            }
+        }
+        assert_eq!(run(ModelKind::Baseline, conditions[2].0).2, 500);
+    }
+
+    #[test]
+    fn wall_clock_budget() {
+        let stop = StopConditions {
+            max_wall_seconds: Some(0.0),
+            ..StopConditions::default()
+        };
+        for kind in ModelKind::all() {
+            let (reason, _, incidence) = run(kind, stop);
+            assert_eq!(reason, StopReason::WallClock);
+            assert!(incidence < EVENTS_PER_CLOCK_CHECK);
+        }
+    }
+}
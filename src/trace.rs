@@ -0,0 +1,239 @@
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+// Status changes per "events" span, so that the trace stays small enough to open
+const EVENTS_PER_BATCH: usize = 1000;
+
+// A complete ("X") event in the Chrome trace format
+#[derive(Serialize, Clone, Debug)]
+pub struct Span {
+    pub name: &'static str,
+    pub cat: &'static str,
+    // Microseconds since the trace started
+    pub ts: f64,
+    pub dur: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<Value>,
+}
+
+// A timeline of what a model spent its time on, for chrome://tracing or
+// https://ui.perfetto.dev. Like the event timers in `ModelStats`, callers
+// start a span with `start_span` and pass it back to `record_span`.
+#[derive(Clone, Debug)]
+pub struct Trace {
+    start: Instant,
+    spans: Vec<Span>,
+    // The batch of events in progress
+    batch_start: Option<Instant>,
+    batch_events: usize,
+    // Short calls made since the last batch closed, recorded as one span each
+    calls: Vec<Calls>,
+}
+
+#[derive(Clone, Debug)]
+struct Calls {
+    name: &'static str,
+    cat: &'static str,
+    first: Instant,
+    count: usize,
+    total: Duration,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            spans: Vec::new(),
+            batch_start: None,
+            batch_events: 0,
+            calls: Vec::new(),
+        }
+    }
+
+    pub fn start_span(&self) -> Instant {
+        Instant::now()
+    }
+
+    pub fn record_span(&mut self, name: &'static str, cat: &'static str, start: Instant) {
+        self.record_span_with(name, cat, start, None);
+    }
+
+    pub fn record_span_with(
+        &mut self,
+        name: &'static str,
+        cat: &'static str,
+        start: Instant,
+        args: Option<Value>,
+    ) {
+        let end = Instant::now();
+        self.spans.push(Span {
+            name,
+            cat,
+            ts: micros(start.duration_since(self.start)),
+            dur: micros(end.duration_since(start)),
+            args,
+        });
+    }
+
+    // Adds a call that's too short and too frequent for a span of its own to
+    // the totals for `name`, which become one span per batch of events
+    pub fn record_call(&mut self, name: &'static str, cat: &'static str, start: Instant) {
+        let duration = start.elapsed();
+        match self.calls.iter_mut().find(|c| c.name == name) {
+            Some(calls) => {
+                calls.count += 1;
+                calls.total += duration;
+            }
+            None => self.calls.push(Calls {
+                name,
+                cat,
+                first: start,
+                count: 1,
+                total: duration,
+            }),
+        }
+    }
+
+    // Counts a status change at time `t`, closing the batch every
+    // `EVENTS_PER_BATCH` events
+    pub fn event(&mut self, t: f64) {
+        let start = *self.batch_start.get_or_insert_with(Instant::now);
+        self.batch_events += 1;
+        if self.batch_events == EVENTS_PER_BATCH {
+            self.close_batch(start, t);
+        }
+    }
+
+    // Closes the last, partial batch at the end of a simulation
+    pub fn finish(&mut self, t: f64) {
+        if let Some(start) = self.batch_start {
+            self.close_batch(start, t);
+        }
+        self.flush_calls();
+    }
+
+    fn close_batch(&mut self, start: Instant, t: f64) {
+        self.flush_calls();
+        let args = json!({ "events": self.batch_events, "time": t });
+        self.record_span_with("events", "events", start, Some(args));
+        self.batch_start = None;
+        self.batch_events = 0;
+    }
+
+    // Starts each span at the first call and makes it as long as all the
+    // calls together
+    fn flush_calls(&mut self) {
+        for calls in std::mem::take(&mut self.calls) {
+            self.spans.push(Span {
+                name: calls.name,
+                cat: calls.cat,
+                ts: micros(calls.first.duration_since(self.start)),
+                dur: micros(calls.total),
+                args: Some(json!({ "calls": calls.count })),
+            });
+        }
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    // Writes the trace as JSON, naming the process after `label`
+    pub fn write_json<W: Write>(&self, mut writer: W, label: &str) -> io::Result<()> {
+        let mut events = vec![json!({
+            "name": "process_name",
+            "ph": "M",
+            "pid": 1,
+            "tid": 1,
+            "args": { "name": label },
+        })];
+        for span in &self.spans {
+            let mut event = serde_json::to_value(span)?;
+            event["ph"] = json!("X");
+            event["pid"] = json!(1);
+            event["tid"] = json!(1);
+            events.push(event);
+        }
+        serde_json::to_writer(&mut writer, &json!({ "traceEvents": events }))?;
+        writeln!(writer)
+    }
+
+    // Writes `<prefix>.json`
+    pub fn write_file(&self, prefix: &str, label: &str) -> io::Result<()> {
+        let file = std::fs::File::create(format!("{prefix}.json"))?;
+        self.write_json(io::BufWriter::new(file), label)
+    }
+}
+
+// Fractional microseconds, the unit of the trace format, to nanosecond precision
+fn micros(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelKind, base::Parameters};
+
+    #[test]
+    fn models_trace_their_phases() {
+        let params = Parameters {
+            population: 5000,
+            disable_queries: false,
+            trace: true,
+            ..Parameters::default()
+        };
+        for kind in ModelKind::all() {
+            let mut model = kind.into_model(params.clone());
+            model.run().unwrap();
+            let trace = model.get_trace().unwrap();
+            let names: Vec<_> = trace.spans().iter().map(|s| s.name).collect();
+            for name in ["setup", "seeding", "simulate", "events"] {
+                assert!(names.contains(&name), "{kind:?} has no {name} span");
+            }
+            let events: u64 = trace
+                .spans()
+                .iter()
+                .filter(|s| s.name == "events")
+                .map(|s| s.args.as_ref().unwrap()["events"].as_u64().unwrap())
+                .sum();
+            let stats = model.get_stats();
+            assert_eq!(
+                events as usize,
+                stats.get_cum_incidence() + stats.get_events().recoveries
+            );
+            if kind == ModelKind::Ixa {
+                assert!(names.contains(&"query_people"));
+                assert!(names.contains(&"sample_person"));
+                // At most one span per kind of call per batch, plus setup
+                let batches = names.iter().filter(|&&n| n == "events").count();
+                let queries = names.iter().filter(|&&n| n == "query_people").count();
+                assert!(queries <= batches + 1);
+                let calls: u64 = trace
+                    .spans()
+                    .iter()
+                    .filter(|s| s.name == "sample_person")
+                    .map(|s| s.args.as_ref().unwrap()["calls"].as_u64().unwrap())
+                    .sum();
+                assert!(calls as usize >= stats.get_cum_incidence());
+            }
+
+            let mut out = Vec::new();
+            trace.write_json(&mut out, &kind.name()).unwrap();
+            let json: Value = serde_json::from_slice(&out).unwrap();
+            let spans = json["traceEvents"].as_array().unwrap();
+            assert_eq!(spans.len(), trace.spans().len() + 1);
+            assert_eq!(spans[1]["ph"], "X");
+        }
+    }
+}
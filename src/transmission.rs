@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Infection {
+    pub infectee: usize,
+    // None for the seeded infections
+    pub infector: Option<usize>,
+    pub time: f64,
+    pub generation: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeSummary {
+    pub infections: usize,
+    pub max_generation: usize,
+    // Mean number of secondary infections per infected person
+    pub mean_offspring: f64,
+    // Mean number of secondary infections per person in each generation
+    pub offspring_by_generation: Vec<f64>,
+    pub mean_generation_interval: f64,
+}
+
+// Serialized as the list of infections; the lookup is rebuilt
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Infection>", into = "Vec<Infection>")]
+pub struct TransmissionTree {
+    infections: Vec<Infection>,
+    // Maps a person to the index of their infection in `infections`
+    lookup: HashMap<usize, usize>,
+}
+
+impl From<Vec<Infection>> for TransmissionTree {
+    fn from(infections: Vec<Infection>) -> Self {
+        let mut tree = Self::new();
+        for infection in infections {
+            tree.push(infection);
+        }
+        tree
+    }
+}
+
+impl From<TransmissionTree> for Vec<Infection> {
+    fn from(tree: TransmissionTree) -> Self {
+        tree.infections
+    }
+}
+
+impl TransmissionTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Removes every infection, keeping the allocated storage
+    pub fn clear(&mut self) {
+        self.infections.clear();
+        self.lookup.clear();
+    }
+
+    pub fn record_seed(&mut self, infectee: usize, time: f64) {
+        self.push(Infection {
+            infectee,
+            infector: None,
+            time,
+            generation: 0,
+        });
+    }
+
+    pub fn record_infection(&mut self, infectee: usize, infector: usize, time: f64) {
+        let generation = self.get(infector).map_or(0, |i| i.generation + 1);
+        self.push(Infection {
+            infectee,
+            infector: Some(infector),
+            time,
+            generation,
+        });
+    }
+
+    fn push(&mut self, infection: Infection) {
+        self.lookup
+            .insert(infection.infectee, self.infections.len());
+        self.infections.push(infection);
+    }
+
+    pub fn get(&self, person: usize) -> Option<&Infection> {
+        self.lookup.get(&person).map(|&i| &self.infections[i])
+    }
+
+    pub fn infections(&self) -> &[Infection] {
+        &self.infections
+    }
+
+    pub fn len(&self) -> usize {
+        self.infections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infections.is_empty()
+    }
+
+    // Time between the infection of the infector and the infectee for every
+    // non-seed infection. Without a symptomatic period, this is both the
+    // generation time and the serial interval.
+    pub fn generation_intervals(&self) -> Vec<f64> {
+        self.infections
+            .iter()
+            .filter_map(|i| {
+                let infector = self.get(i.infector?)?;
+                Some(i.time - infector.time)
+            })
+            .collect()
+    }
+
+    pub fn offspring_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.infections.len()];
+        for infection in &self.infections {
+            if let Some(&parent) = infection.infector.and_then(|p| self.lookup.get(&p)) {
+                counts[parent] += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn summary(&self) -> TreeSummary {
+        let counts = self.offspring_counts();
+        let max_generation = self
+            .infections
+            .iter()
+            .map(|i| i.generation)
+            .max()
+            .unwrap_or(0);
+
+        let mut by_generation = vec![(0usize, 0usize); max_generation + 1];
+        for (infection, &count) in self.infections.iter().zip(&counts) {
+            let entry = &mut by_generation[infection.generation];
+            entry.0 += 1;
+            entry.1 += count;
+        }
+
+        let intervals = self.generation_intervals();
+        TreeSummary {
+            infections: self.infections.len(),
+            max_generation,
+            mean_offspring: mean(&counts.iter().map(|&c| c as f64).collect::<Vec<_>>()),
+            offspring_by_generation: by_generation
+                .iter()
+                .map(|&(people, offspring)| offspring as f64 / people.max(1) as f64)
+                .collect(),
+            mean_generation_interval: mean(&intervals),
+        }
+    }
+
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "infectee,infector,time,generation")?;
+        for i in &self.infections {
+            let infector = i.infector.map(|p| p.to_string()).unwrap_or_default();
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                i.infectee, infector, i.time, i.generation
+            )?;
+        }
+        Ok(())
+    }
+
+    // Writes one Newick tree per seeded infection. Branch lengths are the
+    // time between the infection of the parent and the child.
+    pub fn to_newick(&self) -> String {
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); self.infections.len()];
+        let mut roots = Vec::new();
+        for (index, infection) in self.infections.iter().enumerate() {
+            match infection.infector.and_then(|p| self.lookup.get(&p)) {
+                Some(&parent) => children[parent].push(index),
+                None => roots.push(index),
+            }
+        }
+
+        let mut out = String::new();
+        for root in roots {
+            self.write_newick_node(&mut out, &children, root, None);
+            out.push_str(";\n");
+        }
+        out
+    }
+
+    fn write_newick_node(
+        &self,
+        out: &mut String,
+        children: &[Vec<usize>],
+        index: usize,
+        parent_time: Option<f64>,
+    ) {
+        let infection = &self.infections[index];
+        if !children[index].is_empty() {
+            out.push('(');
+            for (n, &child) in children[index].iter().enumerate() {
+                if n > 0 {
+                    out.push(',');
+                }
+                self.write_newick_node(out, children, child, Some(infection.time));
+            }
+            out.push(')');
+        }
+        write!(out, "{}", infection.infectee).unwrap();
+        if let Some(parent_time) = parent_time {
+            write!(out, ":{}", infection.time - parent_time).unwrap();
+        }
+    }
+
+    // Writes `<prefix>.csv` (an edge list) and `<prefix>.nwk`
+    pub fn write_files(&self, prefix: &str) -> io::Result<()> {
+        let csv = std::fs::File::create(format!("{prefix}.csv"))?;
+        self.write_csv(io::BufWriter::new(csv))?;
+        std::fs::write(format!("{prefix}.nwk"), self.to_newick())
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+
+    use super::*;
+    use crate::rng::DefaultRng;
+    use crate::sir::{baseline, ixa};
+    use crate::{ModelKind, base::Parameters};
+
+    fn example_tree() -> TransmissionTree {
+        let mut tree = TransmissionTree::new();
+        tree.record_seed(0, 0.0);
+        tree.record_seed(1, 0.0);
+        tree.record_infection(2, 0, 1.0);
+        tree.record_infection(3, 0, 2.5);
+        tree.record_infection(4, 2, 3.0);
+        tree
+    }
+
+    #[test]
+    fn generations() {
+        let tree = example_tree();
+        assert_eq!(tree.get(0).unwrap().generation, 0);
+        assert_eq!(tree.get(3).unwrap().generation, 1);
+        assert_eq!(tree.get(4).unwrap().generation, 2);
+        assert_eq!(tree.get(4).unwrap().infector, Some(2));
+    }
+
+    #[test]
+    fn summary() {
+        let summary = example_tree().summary();
+        assert_eq!(summary.infections, 5);
+        assert_eq!(summary.max_generation, 2);
+        assert_eq!(summary.offspring_by_generation, vec![1.0, 0.5, 0.0]);
+        assert_relative_eq!(summary.mean_offspring, 0.6);
+        assert_relative_eq!(summary.mean_generation_interval, 5.5 / 3.0);
+    }
+
+    #[test]
+    fn newick() {
+        assert_eq!(example_tree().to_newick(), "((4:2)2:1,3:2.5)0;\n1;\n");
+    }
+
+    #[test]
+    fn tracking_keeps_the_epidemic() {
+        for common_random_numbers in [false, true] {
+            let params = Parameters {
+                population: 2000,
+                common_random_numbers,
+                ..Parameters::default()
+            };
+            for kind in ModelKind::all() {
+                let outcome = |track_transmission| {
+                    let mut model = kind.into_model(Parameters {
+                        track_transmission,
+                        ..params.clone()
+                    });
+                    model.run().unwrap();
+                    (model.current_time(), *model.get_stats().get_events())
+                };
+                assert_eq!(outcome(false), outcome(true), "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn models_agree() {
+        let params = Parameters {
+            population: 20_000,
+            seed: 1,
+            max_time: 1000.0,
+            track_transmission: true,
+            // One epidemic's mean generation interval varies too much
+            // between seeds to compare two independent runs
+            common_random_numbers: true,
+            ..Parameters::default()
+        };
+        let mut baseline = baseline::Context::<DefaultRng>::new(params.clone());
+        baseline.run().unwrap();
+        let mut ixa = ixa::Model::new(params);
+        ixa.run().unwrap();
+
+        let baseline_tree = baseline.get_transmission_tree().unwrap();
+        let ixa_tree = ixa.get_transmission_tree().unwrap();
+        for tree in [baseline_tree, ixa_tree] {
+            for infection in tree.infections() {
+                if let Some(infector) = infection.infector {
+                    let parent = tree.get(infector).unwrap();
+                    assert!(parent.time <= infection.time);
+                    assert_eq!(parent.generation + 1, infection.generation);
+                }
+            }
+        }
+
+        let baseline_summary = baseline_tree.summary();
+        let ixa_summary = ixa_tree.summary();
+        assert!(baseline_summary.infections > 1000);
+        assert_eq!(baseline_summary.infections, ixa_summary.infections);
+        assert_relative_eq!(
+            baseline_summary.mean_generation_interval,
+            ixa_summary.mean_generation_interval,
+            max_relative = 1e-9
+        );
+    }
+
+    #[test]
+    fn csv() {
+        let mut out = Vec::new();
+        example_tree().write_csv(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "infectee,infector,time,generation");
+        assert_eq!(lines[1], "0,,0,0");
+        assert_eq!(lines[5], "4,2,3,2");
+    }
+}